@@ -0,0 +1,71 @@
+// cargo bench --bench collision
+// brute force vs grid for the queries the server runs every tick, times should stay flat for the grid
+
+use std::hint::black_box;
+use std::time::Instant;
+use rand::Rng;
+
+use sfml_example::shared::*;
+use sfml_example::collision::*;
+use sfml_example::grid::*;
+
+const ROUNDS: u32 = 20;
+
+type Segment = ((f32, f32), (f32, f32));
+
+fn world(entities: usize) -> GameState {
+    let mut rng = rand::thread_rng();
+    let side = (entities as f32).sqrt() * 4.0 * BOX_SIZE; // same density whatever the count
+    let mut game_state = GameState::default();
+    game_state.map.width = side;
+    game_state.map.height = side;
+    for id in 0..entities {
+        game_state.players.push(Player::new(id as u32, rng.gen_range(0.0..side), rng.gen_range(0.0..side)));
+        game_state.boxes.push(WoodBox::new(rng.gen_range(0.0..side), rng.gen_range(0.0..side), 50.0));
+    }
+    game_state
+}
+
+fn segments(game_state: &GameState) -> Vec<Segment> {
+    let mut rng = rand::thread_rng();
+    game_state.players.iter().map(|p| {
+        let (dx, dy) = normalize((rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)));
+        ((p.x, p.y), (p.x + dx * 35.0, p.y + dy * 35.0))
+    }).collect()
+}
+
+fn brute_force(game_state: &GameState, segments: &[Segment]) -> usize {
+    segments.iter().filter(|(from, to)| {
+        game_state.players.iter().any(|p| segment_circle(*from, *to, &p.shape()).is_some())
+            || game_state.boxes.iter().any(|b| segment_rect(*from, *to, &b.shape()).is_some())
+    }).count()
+}
+
+fn with_grid(game_state: &GameState, segments: &[Segment]) -> usize {
+    let grid = SpatialGrid::build(game_state); // counted in, the server rebuilds it every tick
+    segments.iter().filter(|(from, to)| {
+        grid.query_segment(*from, *to).into_iter().any(|entry| match entry {
+            Entry::Player(index) => segment_circle(*from, *to, &game_state.players[index].shape()).is_some(),
+            Entry::Box(index) => segment_rect(*from, *to, &game_state.boxes[index].shape()).is_some(),
+            _ => false
+        })
+    }).count()
+}
+
+fn measure(name: &str, entities: usize, run: impl Fn() -> usize) {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(run());
+    }
+    let per_round = start.elapsed() / ROUNDS;
+    println!("{name:<12} {entities:>6} players + boxes: {per_round:?}");
+}
+
+fn main() {
+    for entities in [50, 200, 1000, 5000] {
+        let game_state = world(entities);
+        let segments = segments(&game_state);
+        measure("brute force", entities, || brute_force(&game_state, &segments));
+        measure("grid", entities, || with_grid(&game_state, &segments));
+    }
+}
@@ -0,0 +1,40 @@
+// cargo bench --bench environment
+// ticks per second the training environment gets through, single and side by side, agents moving and shooting at random
+
+use std::hint::black_box;
+use std::time::Instant;
+use rand::Rng;
+
+use sfml_example::shared::*;
+use sfml_example::command::*;
+use sfml_example::environment::*;
+
+const STEPS: u32 = 2000;
+
+fn random_actions(agents: usize) -> Vec<Vec<Command>> {
+    let mut rng = rand::thread_rng();
+    (0..agents).map(|_| {
+        let (x, y) = normalize((rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)));
+        vec![Command::Input(MoveInput { x, y, ..Default::default() }), Command::Shoot { dx: x, dy: y }]
+    }).collect()
+}
+
+fn measure(name: &str, envs: usize, encoding: Encoding) {
+    let config = EnvConfig { encoding, agents: 2, ..Default::default() };
+    let mut vec_env = VecEnvironment::new(config, envs);
+    vec_env.reset(0);
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        let actions = (0..envs).map(|_| random_actions(2)).collect::<Vec<_>>();
+        black_box(vec_env.step(&actions));
+    }
+    let ticks_per_sec = (STEPS as usize * envs) as f32 / start.elapsed().as_secs_f32();
+    println!("{name:<10} {envs:>3} envs: {ticks_per_sec:>9.0} ticks/s");
+}
+
+fn main() {
+    for envs in [1, 4, 16] {
+        measure("entities", envs, Encoding::Entities);
+        measure("grid", envs, Encoding::Grid);
+    }
+}
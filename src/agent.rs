@@ -0,0 +1,129 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, Mutex};
+use tokio::time::{timeout_at, Duration, Instant};
+
+use crate::shared::*;
+use crate::config::*;
+use crate::command::*;
+use crate::lobby::*;
+
+// bots running as their own processes, one json object per line both ways:
+// the server sends a welcome and the map on connect, the map again whenever it changes and an observation
+// every tick, the agent answers each observation with the commands for that tick within the time budget
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AgentMessage<'a> {
+    Welcome { player_id: u32, tick_millis: u64, budget_millis: u64 },
+    Map { map: &'a Map },
+    Observation { tick: u64, state: &'a GameState }
+}
+
+#[derive(Deserialize)]
+struct AgentReply {
+    tick: u64,
+    commands: Vec<Command>
+}
+
+// the tick loop sends every message once, each agent picks it up from here, with the tick for observations
+pub type AgentFeed = broadcast::Sender<(Option<u64>, Arc<str>)>;
+
+impl AgentMessage<'_> {
+    pub fn to_line(&self) -> Arc<str> {
+        let mut line = serde_json::to_string(self).unwrap();
+        line.push('\n');
+        line.into()
+    }
+}
+
+pub async fn listen_for_agents(
+    port: u16,
+    game_state: Arc<Mutex<GameState>>,
+    config: Arc<ServerConfig>,
+    ids: Arc<IdAllocator>,
+    commands: CommandSender,
+    feed: AgentFeed
+) {
+    // other processes on this machine only
+    let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Failed to listen for agents on {port}: {e}");
+            return;
+        }
+    };
+
+    loop {
+        let Ok((socket, _addr)) = listener.accept().await else { continue };
+        let player_id = ids.allocate();
+        println!("Agent connected as {player_id}");
+        tokio::spawn(handle_agent(socket, player_id, Arc::clone(&game_state), Arc::clone(&config), commands.clone(), feed.subscribe()));
+    }
+}
+
+async fn handle_agent(
+    socket: TcpStream,
+    player_id: u32,
+    game_state: Arc<Mutex<GameState>>,
+    config: Arc<ServerConfig>,
+    commands: CommandSender,
+    mut feed: broadcast::Receiver<(Option<u64>, Arc<str>)>
+) {
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let hello = {
+        let mut game_state = game_state.lock().await;
+        crate::simulation::add_player(&mut game_state, player_id, &config);
+        let welcome = AgentMessage::Welcome { player_id, tick_millis: 1000 / TICKS_PER_SECOND, budget_millis: config.agent_budget_millis };
+        format!("{}{}", welcome.to_line(), AgentMessage::Map { map: &game_state.map }.to_line())
+    };
+
+    let mut timeouts = 0;
+    if writer.write_all(hello.as_bytes()).await.is_ok() {
+        loop {
+            let (tick, line) = match feed.recv().await {
+                Ok(message) => message,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue, // too slow to keep up, skip to the latest
+                Err(broadcast::error::RecvError::Closed) => break
+            };
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+            let Some(tick) = tick else { continue };
+
+            match wait_for_reply(&mut lines, tick, Instant::now() + Duration::from_millis(config.agent_budget_millis)).await {
+                Ok(Some(reply)) => for command in reply {
+                    let _ = commands.send((player_id, command));
+                },
+                // out of time, the player stops until the agent catches up
+                Ok(None) => {
+                    timeouts += 1;
+                    let _ = commands.send((player_id, Command::Input(MoveInput::default())));
+                },
+                Err(()) => break
+            }
+        }
+    }
+
+    game_state.lock().await.remove_player(player_id);
+    println!("Agent {player_id} disconnected, {timeouts} ticks over the time budget");
+}
+
+// Ok(None) - no answer for this tick in time, Err - the agent is gone
+async fn wait_for_reply(lines: &mut tokio::io::Lines<BufReader<tokio::net::tcp::OwnedReadHalf>>, tick: u64, deadline: Instant) -> Result<Option<Vec<Command>>, ()> {
+    loop {
+        match timeout_at(deadline, lines.next_line()).await {
+            Ok(Ok(Some(line))) => match serde_json::from_str::<AgentReply>(&line) {
+                Ok(reply) if reply.tick == tick => return Ok(Some(reply.commands)),
+                Ok(_) => (), // answer to an earlier tick that came too late
+                Err(e) => println!("Invalid agent reply: {e} {line}")
+            },
+            Ok(_) => return Err(()),
+            Err(_) => return Ok(None)
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::f32::consts::TAU;
+use serde::Deserialize;
+
+use crate::shared::*;
+use crate::pickup::*;
+use crate::grid::*;
+use crate::navigation::*;
+use crate::bot::in_line_of_sight;
+
+// utility ai, every action rates how much sense it makes right now, the personality weighs those
+// ratings and the best one gets to decide where the bot goes; shooting is up to the bot itself
+
+const PICKUP_RANGE: f32 = 300.0;
+const COVER_STEP: f32 = 2.0 * NAV_CELL_SIZE;
+
+// what the bot knows when it makes up its mind
+pub struct Situation<'a> {
+    pub game_state: &'a GameState,
+    pub grid: &'a SpatialGrid,
+    pub navigation: &'a NavGrid,
+    pub me: &'a Player,
+    pub nearest_enemy: Option<&'a Player>, // within engagement range
+    pub target_enemy: Option<&'a Player>, // the one being shot at
+    pub roam_target: (f32, f32)
+}
+
+pub enum Goal {
+    Go { to: (f32, f32), sprint: bool },
+    Flee { from: (f32, f32) }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    SeekFlag,
+    FleeWithFlag,
+    ChaseCarrier,
+    Hunt,
+    TakeCover,
+    CollectPickup,
+    Roam
+}
+
+// how much each action is worth to a bot, 1.0 - as rated, 0.0 - never
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct Personality {
+    pub seek_flag: f32,
+    pub flee_with_flag: f32,
+    pub chase_carrier: f32,
+    pub hunt: f32,
+    pub take_cover: f32,
+    pub collect_pickup: f32,
+    pub roam: f32
+}
+
+impl Default for Personality {
+    fn default() -> Self {
+        Personality {
+            seek_flag: 1.0,
+            flee_with_flag: 1.0,
+            chase_carrier: 1.0,
+            hunt: 1.0,
+            take_cover: 1.0,
+            collect_pickup: 1.0,
+            roam: 1.0
+        }
+    }
+}
+
+impl Personality {
+    pub fn weight(&self, action: Action) -> f32 {
+        match action {
+            Action::SeekFlag => self.seek_flag,
+            Action::FleeWithFlag => self.flee_with_flag,
+            Action::ChaseCarrier => self.chase_carrier,
+            Action::Hunt => self.hunt,
+            Action::TakeCover => self.take_cover,
+            Action::CollectPickup => self.collect_pickup,
+            Action::Roam => self.roam
+        }
+    }
+
+    pub fn choose(&self, situation: &Situation) -> Action {
+        Action::ALL.into_iter()
+            .map(|action| (action, action.score(situation) * self.weight(action)))
+            .filter(|(_, score)| *score > 0.0)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(Action::Roam, |(action, _)| action)
+    }
+}
+
+impl<'a> Situation<'a> {
+    fn carrier(&self) -> Option<&'a Player> {
+        self.game_state.players.iter().find(|p| p.has_flag)
+    }
+
+    fn hurt(&self) -> f32 {
+        1.0 - self.me.health / PLAYER_MAX_HEALTH
+    }
+
+    // nearest enemy anywhere on the map
+    fn prey(&self) -> Option<&'a Player> {
+        self.game_state.players.iter()
+            .filter(|p| p.id != self.me.id)
+            .min_by(|a, b| self.distance_to((a.x, a.y)).total_cmp(&self.distance_to((b.x, b.y))))
+    }
+
+    fn distance_to(&self, (x, y): (f32, f32)) -> f32 {
+        get_distance(self.me.x, x, self.me.y, y)
+    }
+
+    fn pickup_value(&self, pickup: &Pickup) -> f32 {
+        let closeness = 1.0 - self.distance_to((pickup.x, pickup.y)) / PICKUP_RANGE;
+        let want = match pickup.kind {
+            PickupKind::HealthPack => 0.2 + 0.6 * self.hurt(),
+            _ => 0.5
+        };
+        want * closeness
+    }
+
+    fn best_pickup(&self) -> Option<(&'a Pickup, f32)> {
+        self.game_state.pickups.iter()
+            .map(|p| (p, self.pickup_value(p)))
+            .filter(|(_, value)| *value > 0.0)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // closest free spot around the bot that the threat can't shoot at
+    fn cover(&self, threat: &Player) -> Option<(f32, f32)> {
+        (1..=3)
+            .flat_map(|ring| (0..16).map(move |step| (ring as f32 * COVER_STEP, step as f32 * TAU / 16.0)))
+            .map(|(distance, angle)| (self.me.x + angle.cos() * distance, self.me.y + angle.sin() * distance))
+            .filter(|&(x, y)| self.navigation.is_free(x, y))
+            .find(|&spot| !in_line_of_sight(self.game_state, self.grid, (threat.x, threat.y), spot))
+    }
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::SeekFlag,
+        Action::FleeWithFlag,
+        Action::ChaseCarrier,
+        Action::Hunt,
+        Action::TakeCover,
+        Action::CollectPickup,
+        Action::Roam
+    ];
+
+    // 0.0 - pointless right now, 1.0 - can't think of anything better
+    pub fn score(self, situation: &Situation) -> f32 {
+        let me = situation.me;
+        let flag_hold = situation.game_state.match_info.mode == GameMode::FlagHold;
+        match self {
+            Action::SeekFlag if flag_hold && situation.carrier().is_none() => 0.6,
+            Action::FleeWithFlag if me.has_flag && situation.nearest_enemy.is_some() => 0.9,
+            Action::ChaseCarrier if situation.carrier().is_some_and(|c| c.id != me.id) => 0.7,
+            Action::Hunt if !me.has_flag && situation.prey().is_some() => 0.3,
+            Action::TakeCover if situation.target_enemy.is_some() => 1.1 * situation.hurt(),
+            Action::CollectPickup => situation.best_pickup().map_or(0.0, |(_, value)| value),
+            Action::Roam if me.has_flag => 0.5,
+            Action::Roam => 0.1,
+            _ => 0.0
+        }
+    }
+
+    pub fn goal(self, situation: &Situation) -> Goal {
+        let game_state = situation.game_state;
+        let go = |to: (f32, f32)| Goal::Go { to, sprint: false };
+        let flee = |from: &Player| Goal::Flee { from: (from.x, from.y) };
+        match self {
+            Action::SeekFlag => go((game_state.flag_x, game_state.flag_y)),
+            Action::FleeWithFlag => situation.nearest_enemy.map_or(go(situation.roam_target), flee),
+            Action::ChaseCarrier => match situation.carrier() {
+                Some(carrier) => Goal::Go { to: (carrier.x, carrier.y), sprint: true },
+                None => go(situation.roam_target)
+            },
+            Action::Hunt => situation.prey().map_or(go(situation.roam_target), |p| go((p.x, p.y))),
+            Action::TakeCover => match situation.target_enemy {
+                Some(threat) => match situation.cover(threat) {
+                    Some(spot) => Goal::Go { to: spot, sprint: true },
+                    None => flee(threat)
+                },
+                None => go(situation.roam_target)
+            },
+            Action::CollectPickup => situation.best_pickup().map_or(go(situation.roam_target), |(p, _)| go((p.x, p.y))),
+            Action::Roam => go(situation.roam_target)
+        }
+    }
+}
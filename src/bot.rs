@@ -1,132 +1,331 @@
-use std::{sync::Arc, time};
-use tokio::{net::tcp::OwnedWriteHalf, sync::Mutex, time::Instant};
 use rand::Rng;
+use serde::Deserialize;
 
 use crate::shared::*;
+use crate::weapon::*;
+use crate::collision::*;
+use crate::grid::*;
+use crate::command::*;
+use crate::navigation::*;
+use crate::behavior::*;
+use crate::brain::*;
+use crate::random::*;
 
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Nightmare
+}
+
+// one entry of the bots in the server config
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct BotSlot {
+    pub brain: Brain,
+    pub difficulty: Difficulty,
+    pub aim_noise: Option<f32>, // radians either way, None - whatever the difficulty comes with
+    pub personality: Personality
+}
+
+pub struct BotSkill {
+    pub reaction_ticks: u64, // how long an enemy has to be in sight before the bot starts shooting at it
+    pub aim_error: f32, // radians either way
+    pub shoot_cooldown_ticks: u64,
+    pub engage_range: f32,
+    pub think_ticks: u64 // the bot looks around and decides again this often
+}
+
+impl Difficulty {
+    pub fn skill(self) -> BotSkill {
+        match self {
+            Difficulty::Easy => BotSkill {
+                reaction_ticks: 12,
+                aim_error: 0.25,
+                shoot_cooldown_ticks: 18,
+                engage_range: 200.0,
+                think_ticks: 6
+            },
+            Difficulty::Normal => BotSkill {
+                reaction_ticks: 6,
+                aim_error: 0.12,
+                shoot_cooldown_ticks: 10,
+                engage_range: 300.0,
+                think_ticks: 3
+            },
+            Difficulty::Hard => BotSkill {
+                reaction_ticks: 3,
+                aim_error: 0.05,
+                shoot_cooldown_ticks: 7,
+                engage_range: 400.0,
+                think_ticks: 2
+            },
+            Difficulty::Nightmare => BotSkill {
+                reaction_ticks: 1,
+                aim_error: 0.0,
+                shoot_cooldown_ticks: 5,
+                engage_range: 500.0,
+                think_ticks: 1
+            }
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            "nightmare" => Some(Difficulty::Nightmare),
+            _ => None
+        }
+    }
+}
+
+// the built-in brain
 #[derive(Clone)]
 pub struct Bot {
-    player_id: u32,
-    target_x: f32,
-    target_y: f32,
-    last_shot_time: Instant
+    slot: BotSlot,
+    roam_target: Option<(f32, f32)>, // where to wander off to when there's nothing better to do
+    path: Vec<(f32, f32)>,
+    path_goal: (f32, f32),
+    path_version: u64,
+    shoot_cooldown: u64,
+    think_cooldown: u64,
+    target_in_sight: Option<(u32, u64)> // enemy id, ticks it's been in sight
 }
 
 impl Bot {
-    const SHOOT_FREQ_MILLIS: u64 = 400;
+    const BOX_CHECK_RANGE: f32 = 50.0;
+    const DASH_RANGE: f32 = 100.0; // flag carrier dashes away from anyone this close
+    const WAYPOINT_RANGE: f32 = 8.0;
 
-    pub fn new(player_id: u32) -> Self {
+    pub fn new(slot: BotSlot) -> Self {
         Bot {
-            player_id: player_id,
-            target_x: rand::thread_rng().gen_range(0.0..WINDOW_SIZE_X as f32),
-            target_y: rand::thread_rng().gen_range(0.0..WINDOW_SIZE_Y as f32),
-            last_shot_time: Instant::now()
+            slot,
+            roam_target: None,
+            path: vec![],
+            path_goal: (0.0, 0.0),
+            path_version: 0,
+            shoot_cooldown: slot.difficulty.skill().shoot_cooldown_ticks,
+            think_cooldown: 0,
+            target_in_sight: None
         }
     }
 
-    pub async fn run(&mut self, game_state: Arc<Mutex<GameState>>, writer: Arc<Mutex<OwnedWriteHalf>>) {
-        let player = Player {
-            id: self.player_id,
-            x: 100.0,
-            y: 100.0,
-            has_flag: false,
-            respawn_num: 0,
-            score: 0,
+}
+
+// in between decisions the last input just keeps going
+impl BotBrain for Bot {
+    fn think(&mut self, observation: &Observation) -> Vec<Command> {
+        let Observation { game_state, navigation, grid, .. } = *observation;
+        let mut commands = vec![];
+        self.shoot_cooldown = self.shoot_cooldown.saturating_sub(1);
+        self.think_cooldown = self.think_cooldown.saturating_sub(1);
+        if self.think_cooldown > 0 {
+            return commands;
+        }
+
+        let skill = self.slot.difficulty.skill();
+        self.think_cooldown = skill.think_ticks;
+        let processed_player = match observation.me() {
+            Some(p) => p,
+            None => return commands
         };
 
-        send_command(&mut *writer.lock().await, CMD_PLAYER, &player).await;
-        loop {
-            let is_present;
-            {
-                is_present = game_state.lock().await.players.iter().any(|p| p.id == player.id);
-            }
-            
-            if is_present {
-                let processed_player = self.update(game_state.clone(), &writer).await;
-                send_command(&mut *writer.lock().await, CMD_PLAYER, &processed_player).await;
-            }
+        let nearby = |radius: f32| grid.query_circle(&Circle { x: processed_player.x, y: processed_player.y, radius });
+        let distance = |p: &Player| get_distance(processed_player.x, p.x, processed_player.y, p.y);
+        let mut enemies: Vec<&Player> = nearby(skill.engage_range).into_iter()
+            .filter_map(|entry| match entry {
+                Entry::Player(index) if game_state.players[index].id != processed_player.id => Some(&game_state.players[index]),
+                _ => None
+            })
+            .filter(|p| distance(p) < skill.engage_range)
+            .collect();
+        enemies.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        let nearest_enemy = enemies.first().copied();
 
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        }
-    }
+        // the flag carrier goes first, otherwise whoever's closest, as long as a bullet can get there
+        let visible: Vec<&Player> = enemies.into_iter()
+            .filter(|p| in_line_of_sight(game_state, grid, (processed_player.x, processed_player.y), (p.x, p.y)))
+            .collect();
+        let target_enemy = visible.iter().find(|p| p.has_flag).or(visible.first()).copied();
 
-    async fn update(&mut self, game_state: Arc<Mutex<GameState>>, writer: &Arc<Mutex<OwnedWriteHalf>>) -> Player {
-        let processed_player;
-        let game_state_clone;
-        {
-            let mut game_state = game_state.lock().await;
-            game_state_clone = game_state.clone();
-            let flag_x = game_state.flag_x;
-            let flag_y = game_state.flag_y;
-
-            let player = game_state.players.iter_mut().find(|p| p.id == self.player_id).unwrap();
-            if !player.has_flag {
-                self.move_towards(flag_x, flag_y, player);
-            } else {
-                self.move_towards(self.target_x, self.target_y, player);
-            }
+        // reaction time starts over whenever the bot switches to someone else
+        self.target_in_sight = match (target_enemy, self.target_in_sight) {
+            (Some(enemy), Some((id, ticks))) if enemy.id == id => Some((id, ticks + skill.think_ticks)),
+            (Some(enemy), _) => Some((enemy.id, 0)),
+            (None, _) => None
+        };
 
-            processed_player = player.clone();
-        }
+        let roam_target = match self.roam_target {
+            Some(target) if get_distance(processed_player.x, target.0, processed_player.y, target.1) > 10.0 => target,
+            _ => *self.roam_target.insert(navigation.random_point())
+        };
+        let situation = Situation {
+            game_state,
+            grid,
+            navigation,
+            me: processed_player,
+            nearest_enemy,
+            target_enemy,
+            roam_target
+        };
+        let (input, destination) = match self.slot.personality.choose(&situation).goal(&situation) {
+            Goal::Go { to, sprint } => (MoveInput { sprint, ..self.steer(to, navigation, processed_player) }, Some(to)),
+            Goal::Flee { from } => (Bot::escape(processed_player, from), None)
+        };
+        commands.push(Command::Input(input));
 
-        if !self.can_shoot() {
-            return processed_player;
+        if self.shoot_cooldown > 0 {
+            return commands;
         }
 
-        for box_item in &game_state_clone.boxes {
-            if self.is_box_blocking_path(&processed_player, box_item) {
-                let (dx, dy) = normalize((box_item.x - processed_player.x, box_item.y - processed_player.y));
-                self.shoot(&processed_player, dx, dy, writer).await;
+        // boxes only get shot when there's no way around them
+        let boxes_in_the_way = match destination {
+            Some(destination) if self.path.is_empty() => nearby(Bot::BOX_CHECK_RANGE).into_iter().map(|entry| (entry, destination)).collect(),
+            _ => vec![]
+        };
+        for (entry, destination) in boxes_in_the_way {
+            let Entry::Box(index) = entry else { continue };
+            let box_item = &game_state.boxes[index];
+            if Bot::is_box_blocking_path(processed_player, box_item, destination) {
+                let (box_x, box_y) = box_item.shape().center();
+                let (dx, dy) = normalize((box_x - processed_player.x, box_y - processed_player.y));
+                self.shoot(dx, dy, &skill, &mut commands);
             }
         }
 
-        for p in &game_state_clone.players {
-            if p.id != processed_player.id {
-                let distance = get_distance(processed_player.x, p.x, processed_player.y, p.y);
-                if distance < 300.0 {
-                    let (dx, dy) = normalize((p.x - processed_player.x, p.y - processed_player.y));
-                    self.shoot(&processed_player, dx, dy, writer).await;
+        if let (Some(enemy), Some((_, ticks))) = (target_enemy, self.target_in_sight) {
+            if ticks >= skill.reaction_ticks {
+                let weapon = Bot::choose_weapon(processed_player, distance(enemy));
+                if weapon != processed_player.weapon {
+                    commands.push(Command::Weapon(WeaponAction::Switch(weapon)));
                 }
+
+                let (dx, dy) = Bot::lead(processed_player, enemy, weapon.stats().bullet_speed);
+                let (dx, dy) = Bot::miss(dx, dy, self.slot.aim_noise.unwrap_or(skill.aim_error));
+                self.shoot(dx, dy, &skill, &mut commands);
             }
         }
+        commands
+    }
+}
 
-        processed_player
+impl Bot {
+    // follows the path to the target, a new one is only looked for when the target moves or the map changes
+    fn steer(&mut self, target: (f32, f32), navigation: &NavGrid, player: &Player) -> MoveInput {
+        let goal_moved = get_distance(self.path_goal.0, target.0, self.path_goal.1, target.1) > NAV_CELL_SIZE;
+        if self.path.is_empty() || goal_moved || self.path_version != navigation.version {
+            self.path = navigation.find_path((player.x, player.y), target).unwrap_or_default();
+            self.path_goal = target;
+            self.path_version = navigation.version;
+        }
+        while self.path.len() > 1 && get_distance(player.x, self.path[0].0, player.y, self.path[0].1) < Bot::WAYPOINT_RANGE {
+            self.path.remove(0);
+        }
+
+        // no way around, straight at it and the boxes in the way get shot
+        let (next_x, next_y) = self.path.first().copied().unwrap_or(target);
+        let (dx, dy) = normalize((next_x - player.x, next_y - player.y));
+        MoveInput { x: dx, y: dy, ..Default::default() }
     }
 
-    fn move_towards(&mut self, target_x: f32, target_y: f32, player: &mut Player) {
-        let (dx, dy) = normalize((target_x - player.x, target_y - player.y));
-        player.x += dx * 5.0;
-        player.y += dy * 5.0;
+    // runs away sprinting, and dashes when someone gets too close
+    fn escape(player: &Player, (from_x, from_y): (f32, f32)) -> MoveInput {
+        let (dx, dy) = normalize((player.x - from_x, player.y - from_y));
+        let distance = get_distance(player.x, from_x, player.y, from_y);
+        MoveInput {
+            x: dx,
+            y: dy,
+            sprint: true,
+            dash: distance < Bot::DASH_RANGE && player.dash_cooldown == 0
+        }
+    }
 
-        if get_distance(player.x, target_x, player.y, target_y) < 10.0 {
-            self.target_x = rand::thread_rng().gen_range(0.0..WINDOW_SIZE_X as f32);
-            self.target_y = rand::thread_rng().gen_range(0.0..WINDOW_SIZE_Y as f32);
+    // where to shoot so the bullet meets the target if it keeps moving the way it does now,
+    // straight at it when the bullet can't catch up
+    fn lead(shooter: &Player, target: &Player, bullet_speed: f32) -> (f32, f32) {
+        let (rx, ry) = (target.x - shooter.x, target.y - shooter.y);
+        let (vx, vy) = (target.vx, target.vy);
+
+        let a = vx * vx + vy * vy - bullet_speed * bullet_speed;
+        let b = 2.0 * (rx * vx + ry * vy);
+        let c = rx * rx + ry * ry;
+        let t = if a.abs() < f32::EPSILON {
+            -c / b
+        } else {
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant < 0.0 {
+                -1.0
+            } else {
+                let (t1, t2) = ((-b - discriminant.sqrt()) / (2.0 * a), (-b + discriminant.sqrt()) / (2.0 * a));
+                if t1 > 0.0 && (t1 < t2 || t2 <= 0.0) { t1 } else { t2 }
+            }
+        };
+
+        if t > 0.0 && t.is_finite() {
+            normalize((rx + vx * t, ry + vy * t))
+        } else {
+            normalize((rx, ry))
+        }
+    }
+
+    // turns the aim by a random angle up to the error either way, the sign of a configured aim_noise doesn't matter
+    // and anything past half a turn is as bad as it gets
+    fn miss(dx: f32, dy: f32, aim_error: f32) -> (f32, f32) {
+        if aim_error.is_nan() || aim_error == 0.0 {
+            return (dx, dy);
         }
+        let aim_error = aim_error.abs().min(std::f32::consts::PI);
+        let angle = rng().gen_range(-aim_error..aim_error);
+        let (sin, cos) = angle.sin_cos();
+        (dx * cos - dy * sin, dx * sin + dy * cos)
     }
 
-    async fn shoot(&mut self, player: &Player, dx: f32, dy: f32, writer: &Arc<Mutex<OwnedWriteHalf>>) {
-        let bullet = Bullet {
-            x: player.x,
-            y: player.y,
-            dx,
-            dy,
-            owner_id: player.id,
+    fn choose_weapon(player: &Player, distance: f32) -> WeaponKind {
+        let preferred = if distance < 80.0 {
+            WeaponKind::Shotgun
+        } else if distance > 200.0 {
+            WeaponKind::Rifle
+        } else if distance > 120.0 {
+            WeaponKind::Grenade
+        } else {
+            WeaponKind::Pistol
         };
-        if self.can_shoot() {
-            send_command(&mut *writer.lock().await, CMD_BULLET, &bullet).await;
-            self.last_shot_time = Instant::now();
+
+        match player.weapons.iter().find(|w| w.kind == preferred) {
+            Some(weapon) if weapon.has_ammo() => preferred,
+            _ => WeaponKind::Pistol
         }
     }
 
-    fn can_shoot(&mut self) -> bool {
-        Instant::now() - self.last_shot_time > time::Duration::from_millis(Bot::SHOOT_FREQ_MILLIS)
+    fn shoot(&mut self, dx: f32, dy: f32, skill: &BotSkill, commands: &mut Vec<Command>) {
+        if self.shoot_cooldown == 0 {
+            commands.push(Command::Shoot { dx, dy });
+            self.shoot_cooldown = skill.shoot_cooldown_ticks;
+        }
     }
 
-    fn is_box_blocking_path(&self, player: &Player, box_item: &WoodBox) -> bool {
-        let bot_to_target_distance = get_distance(player.x, self.target_x, player.y, self.target_y);
-        let bot_to_box_distance = get_distance(player.x, box_item.x, player.y, box_item.y);
-        let box_to_target_distance = get_distance(box_item.x, self.target_x, box_item.y, self.target_y);
+    fn is_box_blocking_path(player: &Player, box_item: &WoodBox, (target_x, target_y): (f32, f32)) -> bool {
+        let bot_to_target_distance = get_distance(player.x, target_x, player.y, target_y);
+        let (box_x, box_y) = box_item.shape().center();
+        let bot_to_box_distance = get_distance(player.x, box_x, player.y, box_y);
+        let box_to_target_distance = get_distance(box_x, target_x, box_y, target_y);
 
-        bot_to_box_distance < 50.0 && bot_to_box_distance + box_to_target_distance < bot_to_target_distance + 10.0 // Allow a small margin of error
+        bot_to_box_distance < Bot::BOX_CHECK_RANGE && bot_to_box_distance + box_to_target_distance < bot_to_target_distance + 10.0 // Allow a small margin of error
     }
 }
+
+// nothing a bullet would stop at between the two points, walls bullets fly through don't count
+pub fn in_line_of_sight(game_state: &GameState, grid: &SpatialGrid, from: (f32, f32), to: (f32, f32)) -> bool {
+    grid.query_segment(from, to).into_iter().all(|entry| match entry {
+        Entry::Box(index) => segment_rect(from, to, &game_state.boxes[index].shape()).is_none(),
+        Entry::Wall(index) => {
+            let wall = &game_state.map.walls[index];
+            game_state.map.obstacle_type(wall).bullets == BulletBehavior::Pass || segment_rect(from, to, &wall.shape()).is_none()
+        },
+        _ => true
+    })
+}
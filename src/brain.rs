@@ -0,0 +1,83 @@
+use serde::Deserialize;
+
+use crate::shared::*;
+use crate::command::*;
+use crate::navigation::*;
+use crate::grid::*;
+use crate::bot::*;
+
+// everything a bot gets to see, the same state every client is sent plus the walkable map
+// and the simulation's grid of where everything is this tick
+pub struct Observation<'a> {
+    pub player_id: u32,
+    pub game_state: &'a GameState,
+    pub navigation: &'a NavGrid,
+    pub grid: &'a SpatialGrid
+}
+
+impl<'a> Observation<'a> {
+    pub fn me(&self) -> Option<&'a Player> {
+        self.game_state.players.iter().find(|p| p.id == self.player_id)
+    }
+}
+
+// asked once a tick what its player should do, the commands go through the same queue as a remote player's
+pub trait BotBrain: Send {
+    fn think(&mut self, observation: &Observation) -> Vec<Command>;
+}
+
+// which ai plays a bot slot, a new one needs a variant here and a line in create
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Brain {
+    #[default]
+    Utility,
+    Rusher,
+    Idle
+}
+
+impl Brain {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "utility" => Some(Brain::Utility),
+            "rusher" => Some(Brain::Rusher),
+            "idle" => Some(Brain::Idle),
+            _ => None
+        }
+    }
+
+    pub fn create(self, slot: BotSlot) -> Box<dyn BotBrain> {
+        match self {
+            Brain::Utility => Box::new(Bot::new(slot)),
+            Brain::Rusher => Box::new(Rusher),
+            Brain::Idle => Box::new(Idle)
+        }
+    }
+}
+
+// stands still, something to measure the others against
+pub struct Idle;
+
+impl BotBrain for Idle {
+    fn think(&mut self, _observation: &Observation) -> Vec<Command> {
+        vec![]
+    }
+}
+
+// runs straight at whoever is closest and keeps shooting, the weapon cooldown sets the pace
+pub struct Rusher;
+
+impl BotBrain for Rusher {
+    fn think(&mut self, observation: &Observation) -> Vec<Command> {
+        let Some(me) = observation.me() else { return vec![] };
+        let nearest = observation.game_state.players.iter()
+            .filter(|p| p.id != me.id)
+            .min_by(|a, b| get_distance(me.x, a.x, me.y, a.y).total_cmp(&get_distance(me.x, b.x, me.y, b.y)));
+        let Some(enemy) = nearest else { return vec![Command::Input(MoveInput::default())] };
+
+        let (dx, dy) = normalize((enemy.x - me.x, enemy.y - me.y));
+        vec![
+            Command::Input(MoveInput { x: dx, y: dy, sprint: true, ..Default::default() }),
+            Command::Shoot { dx, dy }
+        ]
+    }
+}
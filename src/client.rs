@@ -4,18 +4,24 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use sfml::audio::Music;
-use sfml::graphics::{CircleShape, Color, Font, RectangleShape, RenderTarget, RenderWindow, Shape, Text, Transformable};
-use sfml::system::Vector2f;
+use sfml::graphics::{CircleShape, Color, FloatRect, Font, RectangleShape, RenderTarget, RenderWindow, Shape, Text, Transformable, View};
+use sfml::system::{Vector2f, Vector2i};
+use sfml::SfBox;
 use sfml::window::mouse::Button;
-use sfml::window::{ContextSettings, Event, Key, Style};
+use sfml::window::{ContextSettings, Event, Key, Style, VideoMode};
+use serde::Serialize;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 use std::process::{Command as StdCommand, Child};
 
-mod shared;
-use crate::shared::*;
+use sfml_example::shared::*;
+use sfml_example::weapon::*;
+use sfml_example::pickup::*;
+use sfml_example::config::load_json_or_default;
+mod settings;
+use crate::settings::*;
 use tokio::net::TcpStream;
 
 enum Scene { Menu, Game }
@@ -27,20 +33,35 @@ const LOCAL_ADDR: &str = "127.0.0.1:54321";
 const ADDR_FILE_PATH: &str = "addr.txt";
 const GAME_TITLE: &str = "Flag Frenzy";
 
-async fn read(mut reader: &mut Option<OwnedReadHalf>, game_state_clone: &Arc<Mutex<GameState>>) {
+async fn read(mut reader: &mut Option<OwnedReadHalf>, game_state_clone: &Arc<Mutex<GameState>>, map_clone: &Arc<Mutex<Map>>) {
     let mut buffer: Vec<u8> = vec![0; 4096];
+    let mut pending = Vec::new();
     loop {
         if let Some(r) = &mut reader {
             match (*r).read(&mut buffer).await {
                 Ok(n) if n > 0 => {
-                    let response = serde_json::from_slice::<GameState>(&buffer[..n]);
-                    match response {
-                        Ok(x) => {
-                            let mut game_state = game_state_clone.lock().await;
-                            *game_state = x;
-                        },
-                        _ => {
-                            println!("Invalid packet received, ignoring {}", std::str::from_utf8(&buffer).unwrap());
+                    pending.extend_from_slice(&buffer[..n]);
+                    let mut latest_state = None;
+                    for command in take_commands(&mut pending) {
+                        if command.starts_with(CMD_MAP) {
+                            match serde_json::from_slice::<Map>(&command[CMD_MAP.len()..]) {
+                                Ok(x) => *map_clone.lock().await = x,
+                                _ => println!("Invalid map received, ignoring")
+                            }
+                        } else if command.starts_with(CMD_STATE) {
+                            latest_state = Some(command); // only the newest snapshot matters, older ones are already stale
+                        }
+                    }
+
+                    if let Some(command) = latest_state {
+                        match serde_json::from_slice::<GameState>(&command[CMD_STATE.len()..]) {
+                            Ok(x) => {
+                                let mut game_state = game_state_clone.lock().await;
+                                *game_state = x;
+                            },
+                            _ => {
+                                println!("Invalid packet received, ignoring {}", String::from_utf8_lossy(&command));
+                            }
                         }
                     }
                 }
@@ -55,27 +76,16 @@ async fn read(mut reader: &mut Option<OwnedReadHalf>, game_state_clone: &Arc<Mut
 #[tokio::main]
 async fn main() {
     let mut scene = Scene::Menu;
-    let mut window = RenderWindow::new(
-        (WINDOW_SIZE_X, WINDOW_SIZE_Y),
-        GAME_TITLE,
-        Style::CLOSE,
-        &ContextSettings::default(),
-    );
-    window.set_vertical_sync_enabled(true);
+    let settings: ClientSettings = load_json_or_default(SETTINGS_PATH);
+    let mut window = create_window(&settings);
 
     let font = Font::from_file(FONT_PATH).expect("Failed to load font");
     let mut bg_music = Music::from_file(MUSIC_PATH).expect("Failed to load background music");
     bg_music.set_looping(true);
     bg_music.play();
 
-    let game_state = Arc::new(Mutex::new(GameState {
-        players: vec![],
-        flag_x: Default::default(),
-        flag_y: Default::default(),
-        flag_owner_id: Default::default(),
-        bullets: vec![],
-        boxes: vec![],
-    }));
+    let game_state = Arc::new(Mutex::new(GameState::default()));
+    let map = Arc::new(Mutex::new(Map::default()));
 
     let mut reader: Option<OwnedReadHalf>;
     let mut writer: Option<OwnedWriteHalf> = None;
@@ -84,25 +94,37 @@ async fn main() {
 
     while window.is_open() {
         window.clear(Color::BLACK);
+        window.set_view(&hud_view(&window));
 
         match scene {
             Scene::Game => {
-                let mut game_state_clone: GameState;
+                let game_state_clone: GameState;
                 { //critical section to get snapshot of game state
                     let game_state = game_state.lock().await;
                     game_state_clone = game_state.clone();
                 }
+                let map_clone = map.lock().await.clone();
+                let camera = camera_view(&window, &game_state_clone, &map_clone, player_id);
 
                 if let Some(w) = &mut writer {
+                    let match_over = game_state_clone.match_info.phase == MatchPhase::PostMatch;
                     while let Some(event) = window.poll_event() {
                         if event == Event::Closed {
                             window.close();
                         }
-                        
-                        handle_game_event(w, &game_state_clone, player_id, &event).await;
+
+                        if match_over {
+                            handle_vote_event(w, &game_state_clone, &event).await;
+                        } else {
+                            handle_game_event(w, &game_state_clone, player_id, &event, &window, &camera).await;
+                        }
+                    }
+
+                    if match_over {
+                        render_vote_menu(&mut window, &game_state_clone, player_id, &font);
+                    } else {
+                        render_game(&mut window, &game_state_clone, &map_clone, &camera, player_id, &font);
                     }
-    
-                    render_game(&mut window, w, &mut game_state_clone, player_id, &font).await;
                 }
             }
             Scene::Menu => {
@@ -125,8 +147,9 @@ async fn main() {
                                 reader = Some(r);
                                 writer = Some(w);
                                 let game_state_clone = Arc::clone(&game_state);
+                                let map_clone = Arc::clone(&map);
                                 tokio::spawn(async move {
-                                        read(&mut reader, &game_state_clone).await;
+                                        read(&mut reader, &game_state_clone, &map_clone).await;
                                     }
                                 );
                                 scene = Scene::Game;
@@ -136,8 +159,9 @@ async fn main() {
                                 reader = Some(r);
                                 writer = Some(w);
                                 let game_state_clone = Arc::clone(&game_state);
+                                let map_clone = Arc::clone(&map);
                                 tokio::spawn(async move {
-                                        read(&mut reader, &game_state_clone).await;
+                                        read(&mut reader, &game_state_clone, &map_clone).await;
                                     }
                                 );
                                 scene = Scene::Game;
@@ -183,25 +207,69 @@ async fn connect(player_id: &mut u32, read_addr: bool) -> (OwnedReadHalf, OwnedW
     return (r, w);
 }
 
-async fn handle_game_event(mut writer: &mut OwnedWriteHalf, game_state_clone: &GameState, player_id: u32, event: &Event) {
-    if !game_state_clone.players.iter().any(|p| p.id == player_id) {
-        return;
-    }
-    
-    if let Event::MouseButtonPressed { button, x, y } = *event {
-        if button == Button::Left {
-            let player = game_state_clone.players.iter().find(|p| p.id == player_id).unwrap();
-            let (dx, dy) = normalize(((x as f32 - player.x), (y as f32 - player.y)));
-            let bullet = Bullet {
-                x: player.x,
-                y: player.y,
-                dx,
-                dy,
-                owner_id: player.id,
+// the server knows who's on the other end of the socket, commands don't carry a player id
+async fn send_command<T>(writer: &mut OwnedWriteHalf, cmd: &[u8], obj: &T) where T: Serialize {
+    writer.write_all(&encode_command(cmd, obj)).await.unwrap();
+}
+
+async fn handle_game_event(writer: &mut OwnedWriteHalf, game_state_clone: &GameState, player_id: u32, event: &Event, window: &RenderWindow, camera: &View) {
+    let player = match game_state_clone.players.iter().find(|p| p.id == player_id) {
+        Some(p) => p,
+        None => return
+    };
+
+    match *event {
+        Event::MouseButtonPressed { button: Button::Left, x, y } => {
+            let target = window.map_pixel_to_coords(Vector2i::new(x, y), camera);
+            let (dx, dy) = normalize(((target.x - player.x), (target.y - player.y)));
+            send_command(writer, CMD_SHOOT, &Shot { dx, dy }).await;
+        },
+        Event::KeyPressed { code: Key::W | Key::A | Key::S | Key::D | Key::LShift, .. }
+        | Event::KeyReleased { code: Key::W | Key::A | Key::S | Key::D | Key::LShift, .. } => {
+            send_command(writer, CMD_INPUT, &InputCommand { input: move_input() }).await;
+        },
+        Event::KeyPressed { code: Key::Space, .. } => {
+            let input = MoveInput { dash: true, ..move_input() };
+            send_command(writer, CMD_INPUT, &InputCommand { input }).await;
+        },
+        Event::LostFocus => {
+            send_command(writer, CMD_INPUT, &InputCommand { input: MoveInput::default() }).await;
+        },
+        Event::KeyPressed { code, .. } => {
+            let action = match code {
+                Key::Num1 => Some(WeaponAction::Switch(WeaponKind::Pistol)),
+                Key::Num2 => Some(WeaponAction::Switch(WeaponKind::Shotgun)),
+                Key::Num3 => Some(WeaponAction::Switch(WeaponKind::Rifle)),
+                Key::Num4 => Some(WeaponAction::Switch(WeaponKind::Grenade)),
+                Key::R => Some(WeaponAction::Reload),
+                _ => None
             };
-            
-            send_command(&mut writer, CMD_BULLET, &bullet).await;
-        }
+
+            if let Some(action) = action {
+                send_command(writer, CMD_WEAPON, &WeaponCommand { action }).await;
+            }
+        },
+        Event::MouseWheelScrolled { delta, .. } => {
+            let current = WeaponKind::ALL.iter().position(|k| *k == player.weapon).unwrap_or(0);
+            let step = if delta > 0.0 { WeaponKind::ALL.len() - 1 } else { 1 };
+            let next = WeaponKind::ALL[(current + step) % WeaponKind::ALL.len()];
+            let action = WeaponAction::Switch(next);
+            send_command(writer, CMD_WEAPON, &WeaponCommand { action }).await;
+        },
+        _ => ()
+    }
+}
+
+// opposite keys cancel out, the server takes care of diagonals
+fn move_input() -> MoveInput {
+    let axis = |negative: Key, positive: Key| {
+        (Key::is_pressed(positive) as i32 - Key::is_pressed(negative) as i32) as f32
+    };
+    MoveInput {
+        x: axis(Key::A, Key::D),
+        y: axis(Key::W, Key::S),
+        sprint: Key::is_pressed(Key::LShift),
+        dash: false
     }
 }
 
@@ -212,55 +280,77 @@ fn render_menu(window: &mut RenderWindow, font: &Font) {
     draw_centered_text("3 - Quit", 350.0, window, font, 30);
 }
 
+async fn handle_vote_event(writer: &mut OwnedWriteHalf, game_state_clone: &GameState, event: &Event) {
+    let option = match *event {
+        Event::KeyPressed { code: Key::Num1, .. } => 0,
+        Event::KeyPressed { code: Key::Num2, .. } => 1,
+        Event::KeyPressed { code: Key::Num3, .. } => 2,
+        Event::KeyPressed { code: Key::Num4, .. } => 3,
+        Event::KeyPressed { code: Key::Num5, .. } => 4,
+        _ => return
+    };
+
+    if option < game_state_clone.match_info.vote_options.len() {
+        send_command(writer, CMD_VOTE, &Vote { option }).await;
+    }
+}
+
+fn render_vote_menu(window: &mut RenderWindow, game_state_clone: &GameState, player_id: u32, font: &Font) {
+    let match_info = &game_state_clone.match_info;
+    let title = match match_info.winner_id {
+        Some(id) if id == player_id => String::from("You won!"),
+        Some(id) => format!("Player {id} won"),
+        None => String::from("Match over")
+    };
+    draw_centered_text(&title, 80.0, window, font, 50);
+
+    let mut players = game_state_clone.players.clone();
+    players.sort_by_key(|p| -p.score);
+    for (index, p) in players.iter().take(5).enumerate() {
+        draw_centered_text(&format!("{}: {}", p.id, p.score), 160.0 + 25.0 * index as f32, window, font, 20);
+    }
+
+    let seconds_left = match_info.ticks_left / TICKS_PER_SECOND;
+    if match_info.vote_options.is_empty() {
+        draw_centered_text(&format!("Next match in {seconds_left}s"), 320.0, window, font, 30);
+        return;
+    }
+
+    draw_centered_text(&format!("Vote for the next match ({seconds_left}s)"), 310.0, window, font, 30);
+    let my_vote = match_info.votes.get(&player_id);
+    for (index, option) in match_info.vote_options.iter().enumerate() {
+        let votes = match_info.votes.values().filter(|v| **v == index).count();
+        let marker = if my_vote == Some(&index) { ">" } else { " " };
+        let line = format!("{marker} {} - {option} [{votes}]", index + 1);
+        draw_centered_text(&line, 360.0 + 40.0 * index as f32, window, font, 24);
+    }
+}
+
 fn draw_centered_text(text: &str, y: f32, window: &mut RenderWindow, font: &Font, font_size: u32) {
     let mut quit_option = Text::new(text, font, font_size);
     quit_option.set_fill_color(Color::WHITE);
-    quit_option.set_position((get_text_center_x(&quit_option), y));
+    let top = (window.size().y as f32 - WINDOW_SIZE_Y as f32) / 2.0; // menus are laid out for 800x600, keep them in the middle
+    quit_option.set_position((get_text_center_x(&quit_option, window), top + y));
     window.draw(&quit_option);
 }
 
-fn get_text_center_x(text: &Text) -> f32 {
-    (WINDOW_SIZE_X as f32 - text.global_bounds().width) / 2.0
+fn get_text_center_x(text: &Text, window: &RenderWindow) -> f32 {
+    (window.size().x as f32 - text.global_bounds().width) / 2.0
 }
 
-async fn render_game(window: &mut RenderWindow, mut writer: &mut OwnedWriteHalf, game_state_clone: &mut GameState, player_id: u32, font: &Font) {
-    let player_option = game_state_clone.players.iter_mut().find(|p| p.id == player_id);
-    let player;
-    match player_option {
-        None => return,
-        _ => player = player_option.unwrap()
-    }
-    let player_clone = player.clone(); //😐
+fn render_game(window: &mut RenderWindow, game_state_clone: &GameState, map: &Map, camera: &View, player_id: u32, font: &Font) {
+    let player_clone = match game_state_clone.players.iter().find(|p| p.id == player_id) {
+        Some(p) => p.clone(),
+        None => return
+    };
 
-    let mut dx = 0.0;
-    let mut dy = 0.0;
-    if player.y > 0.0 && Key::is_pressed(Key::W) {
-        dy = -5.0;
-    }
-    else if player.y < WINDOW_SIZE_Y as f32 - PLAYER_RADIUS && Key::is_pressed(Key::S) {
-        dy = 5.0;
-    }
-    if player.x > 0.0 && Key::is_pressed(Key::A) {
-        dx = -5.0;
-    }
-    else if player.x < WINDOW_SIZE_X as f32 - PLAYER_RADIUS && Key::is_pressed(Key::D) {
-        dx = 5.0;
-    }
-    
-    player.x += dx;
-    player.y += dy;
-    for box_item in &game_state_clone.boxes {
-        if get_distance(player.x, box_item.x, player.y, box_item.y) < PLAYER_RADIUS {
-            player.x -= dx;
-            player.y -= dy;
-        }
-    }
-
-    send_command(&mut writer, CMD_PLAYER, &player).await;
+    window.set_view(camera);
+    draw_map(window, map);
 
     for player in &game_state_clone.players {
-        let mut circle = CircleShape::new(10.0, 30);
-        circle.set_position(Vector2f::new(player.x , player.y));
+        let mut circle = CircleShape::new(PLAYER_RADIUS, 30);
+        circle.set_origin(Vector2f::new(PLAYER_RADIUS, PLAYER_RADIUS));
+        circle.set_position(Vector2f::new(player.x, player.y));
         if player.has_flag {
             circle.set_fill_color(Color::RED);
         } else if player.id == player_clone.id {
@@ -268,34 +358,122 @@ async fn render_game(window: &mut RenderWindow, mut writer: &mut OwnedWriteHalf,
         } else {
             circle.set_fill_color(Color::GREEN);
         }
+        if player.spawn_protection > 0 {
+            // blinking ghost while nobody can hurt it
+            let mut color = circle.fill_color();
+            color.a = if (player.spawn_protection / 4) % 2 == 0 { 90 } else { 180 };
+            circle.set_fill_color(color);
+            circle.set_outline_color(Color::WHITE);
+            circle.set_outline_thickness(1.0);
+        }
         window.draw(&circle);
+        if player.has_effect(PickupKind::Shield) {
+            let mut shield = CircleShape::new(PLAYER_RADIUS + 3.0, 30);
+            shield.set_origin(Vector2f::new(PLAYER_RADIUS + 3.0, PLAYER_RADIUS + 3.0));
+            shield.set_position(Vector2f::new(player.x, player.y));
+            shield.set_fill_color(Color::TRANSPARENT);
+            shield.set_outline_color(pickup_color(PickupKind::Shield));
+            shield.set_outline_thickness(2.0);
+            window.draw(&shield);
+        }
+        draw_health_bar(window, player);
     }
 
     for bullet in &game_state_clone.bullets {
-        let mut circle = CircleShape::new(5.0, 30);
-        circle.set_position(Vector2f::new(bullet.x , bullet.y));
-        circle.set_fill_color(Color::MAGENTA);
+        let radius = if bullet.is_explosive() { 7.0 } else { 5.0 };
+        let mut circle = CircleShape::new(radius, 30);
+        circle.set_origin(Vector2f::new(radius, radius));
+        circle.set_position(Vector2f::new(bullet.x, bullet.y));
+        circle.set_fill_color(if bullet.is_explosive() { Color::rgb(255, 140, 0) } else { Color::MAGENTA });
         window.draw(&circle);
     }
-    
-    for box_item in &game_state_clone.boxes {
+
+    for wall in &map.walls {
         let mut rect = RectangleShape::new();
         rect.set_size(Vector2f::new(BOX_SIZE, BOX_SIZE));
-        rect.set_position(Vector2f::new(box_item.x, box_item.y));
-        rect.set_fill_color(Color::YELLOW);
+        rect.set_position(Vector2f::new(wall.x, wall.y));
+        rect.set_fill_color(obstacle_color(map.obstacle_type(wall)));
         window.draw(&rect);
     }
 
-    let mut flag = CircleShape::new(FLAG_SIZE, 30);
-    flag.set_position(Vector2f::new(game_state_clone.flag_x, game_state_clone.flag_y));
-    flag.set_fill_color(Color::BLUE);
-    window.draw(&flag);
+    for box_item in &game_state_clone.boxes {
+        draw_box(window, box_item);
+    }
+
+    for event in &game_state_clone.events {
+        draw_event(window, event);
+    }
 
-    let mut player_score_text = Text::new(&format!("You ({}): {}",player_clone.id, player_clone.score), &font, 16);
+    for pickup in &game_state_clone.pickups {
+        let mut diamond = CircleShape::new(PICKUP_SIZE / 2.0, 4);
+        diamond.set_origin(Vector2f::new(PICKUP_SIZE / 2.0, PICKUP_SIZE / 2.0));
+        diamond.set_position(Vector2f::new(pickup.x, pickup.y));
+        diamond.set_fill_color(pickup_color(pickup.kind));
+        window.draw(&diamond);
+    }
+
+    if game_state_clone.match_info.mode == GameMode::FlagHold {
+        let mut flag = CircleShape::new(FLAG_SIZE, 30);
+        flag.set_origin(Vector2f::new(FLAG_SIZE, FLAG_SIZE));
+        flag.set_position(Vector2f::new(game_state_clone.flag_x, game_state_clone.flag_y));
+        flag.set_fill_color(Color::BLUE);
+        window.draw(&flag);
+    }
+
+    window.set_view(&hud_view(window));
+    draw_minimap(window, game_state_clone, map, camera, player_id);
+    let (screen_width, screen_height) = (window.size().x as f32, window.size().y as f32);
+
+    let mut player_score_text = Text::new(&format!("You ({}): {}   HP: {:.0}   Armor: {:.0}",
+        player_clone.id, player_clone.score, player_clone.health, player_clone.armor), font, 16);
     player_score_text.set_fill_color(Color::WHITE);
     player_score_text.set_position((20.0, 20.0));
     window.draw(&player_score_text);
 
+    let weapon = player_clone.weapon();
+    let reserve = match weapon.reserve {
+        Some(reserve) => reserve.to_string(),
+        None => String::from("inf")
+    };
+    let weapon_status = if weapon.reload > 0 {
+        format!("{} - reloading...", weapon.kind.name())
+    } else {
+        format!("{} {}/{}", weapon.kind.name(), weapon.ammo, reserve)
+    };
+    let meter_x = screen_width / 2.0 - 75.0;
+    draw_meter(window, (meter_x, screen_height - 40.0), player_clone.stamina / PLAYER_MAX_STAMINA, Color::rgb(255, 200, 0));
+    let dash_ready = 1.0 - player_clone.dash_cooldown as f32 / DASH_COOLDOWN_TICKS as f32;
+    draw_meter(window, (meter_x, screen_height - 28.0), dash_ready, if dash_ready >= 1.0 { Color::CYAN } else { Color::rgb(0, 100, 120) });
+
+    let mut weapon_text = Text::new(&weapon_status, font, 16);
+    weapon_text.set_fill_color(Color::WHITE);
+    weapon_text.set_position((20.0, screen_height - 40.0));
+    window.draw(&weapon_text);
+
+    if player_clone.spawn_protection > 0 {
+        let seconds_left = player_clone.spawn_protection as f32 / TICKS_PER_SECOND as f32;
+        let mut protection_text = Text::new(&format!("Spawn protection {:.1}s", seconds_left), font, 16);
+        protection_text.set_fill_color(Color::WHITE);
+        protection_text.set_position((20.0, screen_height - 90.0));
+        window.draw(&protection_text);
+    }
+
+    let seconds_left = game_state_clone.match_info.ticks_left / TICKS_PER_SECOND;
+    let match_status = format!("{:?} - {}:{:02} - first to {}", game_state_clone.match_info.mode,
+        seconds_left / 60, seconds_left % 60, game_state_clone.match_info.score_limit);
+    let mut match_text = Text::new(&match_status, font, 16);
+    match_text.set_fill_color(Color::WHITE);
+    match_text.set_position((screen_width - match_text.global_bounds().width - 20.0, 20.0));
+    window.draw(&match_text);
+
+    for (index, effect) in player_clone.effects.iter().enumerate() {
+        let seconds_left = effect.ticks_left as f32 / TICKS_PER_SECOND as f32;
+        let mut effect_text = Text::new(&format!("{} {:.1}s", pickup_name(effect.kind), seconds_left), font, 16);
+        effect_text.set_fill_color(pickup_color(effect.kind));
+        effect_text.set_position((20.0 + 120.0 * index as f32, screen_height - 65.0));
+        window.draw(&effect_text);
+    }
+
     let column_height = 20.0;
     for (index, p) in game_state_clone.players.iter().enumerate() {
         let mut player_score_text = Text::new(&format!("{}: {}",p.id, p.score), &font, 16);
@@ -303,4 +481,241 @@ async fn render_game(window: &mut RenderWindow, mut writer: &mut OwnedWriteHalf,
         player_score_text.set_position((20.0, 20.0 + (column_height * (index + 1) as f32)));
         window.draw(&player_score_text);
     }
-}
\ No newline at end of file
+}
+fn draw_health_bar(window: &mut RenderWindow, player: &Player) {
+    let bar_width = PLAYER_RADIUS * 2.0;
+    let bar_height = 3.0;
+    let bar_x = player.x - PLAYER_RADIUS;
+    let bar_y = player.y - PLAYER_RADIUS - 8.0;
+
+    let mut background = RectangleShape::new();
+    background.set_size(Vector2f::new(bar_width, bar_height));
+    background.set_position(Vector2f::new(bar_x, bar_y));
+    background.set_fill_color(Color::rgb(80, 0, 0));
+    window.draw(&background);
+
+    let mut health = RectangleShape::new();
+    health.set_size(Vector2f::new(bar_width * player.health / PLAYER_MAX_HEALTH, bar_height));
+    health.set_position(Vector2f::new(bar_x, bar_y));
+    health.set_fill_color(Color::GREEN);
+    window.draw(&health);
+
+    if player.armor > 0.0 {
+        let mut armor = RectangleShape::new();
+        armor.set_size(Vector2f::new(bar_width * player.armor / PLAYER_MAX_ARMOR, bar_height - 1.0));
+        armor.set_position(Vector2f::new(bar_x, bar_y - bar_height));
+        armor.set_fill_color(Color::rgb(150, 150, 255));
+        window.draw(&armor);
+    }
+}
+
+// stamina and dash cooldown, full when usable
+fn draw_meter(window: &mut RenderWindow, (x, y): (f32, f32), fraction: f32, color: Color) {
+    let size = Vector2f::new(150.0, 8.0);
+    let mut background = RectangleShape::new();
+    background.set_size(size);
+    background.set_position(Vector2f::new(x, y));
+    background.set_fill_color(Color::rgb(40, 40, 40));
+    window.draw(&background);
+
+    let mut fill = RectangleShape::new();
+    fill.set_size(Vector2f::new(size.x * fraction.clamp(0.0, 1.0), size.y));
+    fill.set_position(Vector2f::new(x, y));
+    fill.set_fill_color(color);
+    window.draw(&fill);
+}
+
+fn pickup_name(kind: PickupKind) -> &'static str {
+    match kind {
+        PickupKind::SpeedBoost => "Speed",
+        PickupKind::Shield => "Shield",
+        PickupKind::RapidFire => "Rapid fire",
+        PickupKind::HealthPack => "Health"
+    }
+}
+
+fn pickup_color(kind: PickupKind) -> Color {
+    match kind {
+        PickupKind::SpeedBoost => Color::rgb(0, 200, 255),
+        PickupKind::Shield => Color::rgb(180, 180, 255),
+        PickupKind::RapidFire => Color::rgb(255, 80, 80),
+        PickupKind::HealthPack => Color::rgb(80, 255, 80)
+    }
+}
+
+fn obstacle_color(obstacle: &ObstacleType) -> Color {
+    let (r, g, b) = obstacle.color;
+    Color::rgb(r, g, b)
+}
+
+fn draw_box(window: &mut RenderWindow, box_item: &WoodBox) {
+    let condition = (box_item.health / box_item.max_health).clamp(0.0, 1.0);
+    let mut rect = RectangleShape::new();
+    rect.set_size(Vector2f::new(BOX_SIZE, BOX_SIZE));
+    rect.set_position(Vector2f::new(box_item.x, box_item.y));
+    rect.set_fill_color(Color::rgb(255, (140.0 + 115.0 * condition) as u8, 0));
+    window.draw(&rect);
+
+    // cracks, one more for every third of health lost
+    let cracks = ((1.0 - condition) * 3.0) as u32;
+    for i in 0..cracks {
+        let mut crack = RectangleShape::new();
+        crack.set_size(Vector2f::new(BOX_SIZE * 0.8, 2.0));
+        crack.set_position(Vector2f::new(box_item.x + 2.0, box_item.y + 4.0 + 5.0 * i as f32));
+        crack.set_rotation(if i % 2 == 0 { 20.0 } else { -15.0 });
+        crack.set_fill_color(Color::rgb(100, 60, 0));
+        window.draw(&crack);
+    }
+}
+
+fn draw_event(window: &mut RenderWindow, event: &GameEvent) {
+    let alpha = (200 * event.ticks_left / EVENT_TICKS) as u8;
+    match event.kind {
+        GameEventKind::Explosion { radius } => {
+            let mut circle = CircleShape::new(radius, 30);
+            circle.set_position(Vector2f::new(event.x - radius, event.y - radius));
+            circle.set_fill_color(Color::rgba(255, 120, 0, alpha));
+            window.draw(&circle);
+        },
+        GameEventKind::BoxDestroyed => {
+            let spread = (EVENT_TICKS - event.ticks_left) as f32 * 2.0;
+            for (dx, dy) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+                let mut splinter = RectangleShape::new();
+                splinter.set_size(Vector2f::new(BOX_SIZE / 3.0, BOX_SIZE / 3.0));
+                splinter.set_origin(Vector2f::new(BOX_SIZE / 6.0, BOX_SIZE / 6.0));
+                splinter.set_position(Vector2f::new(event.x + dx * spread, event.y + dy * spread));
+                splinter.set_fill_color(Color::rgba(200, 140, 0, alpha));
+                window.draw(&splinter);
+            }
+        }
+    }
+}
+
+fn draw_map(window: &mut RenderWindow, map: &Map) {
+    let mut floor = RectangleShape::new();
+    floor.set_size(Vector2f::new(map.width, map.height));
+    floor.set_fill_color(Color::rgb(20, 20, 28));
+    window.draw(&floor);
+
+    for sp in &map.spawn_points {
+        let mut marker = CircleShape::new(BOX_SIZE / 2.0, 6);
+        marker.set_origin(Vector2f::new(BOX_SIZE / 2.0, BOX_SIZE / 2.0));
+        marker.set_position(Vector2f::new(sp.x, sp.y));
+        marker.set_fill_color(Color::TRANSPARENT);
+        marker.set_outline_color(Color::rgb(50, 50, 90));
+        marker.set_outline_thickness(1.0);
+        window.draw(&marker);
+    }
+
+    for (x, y) in &map.pickup_spots {
+        let mut marker = CircleShape::new(PICKUP_SIZE / 2.0, 4);
+        marker.set_origin(Vector2f::new(PICKUP_SIZE / 2.0, PICKUP_SIZE / 2.0));
+        marker.set_position(Vector2f::new(*x, *y));
+        marker.set_fill_color(Color::rgb(40, 40, 55));
+        window.draw(&marker);
+    }
+}
+
+fn create_window(settings: &ClientSettings) -> RenderWindow {
+    let (mode, style) = if settings.fullscreen {
+        (VideoMode::desktop_mode(), Style::FULLSCREEN)
+    } else if settings.resizable {
+        (VideoMode::from((settings.width, settings.height)), Style::DEFAULT)
+    } else {
+        (VideoMode::from((settings.width, settings.height)), Style::CLOSE)
+    };
+
+    let mut window = RenderWindow::new(mode, GAME_TITLE, style, &ContextSettings::default());
+    window.set_vertical_sync_enabled(settings.vsync);
+    window
+}
+
+// 1:1 with window pixels, HUD sticks to the edges whatever the size
+fn hud_view(window: &RenderWindow) -> SfBox<View> {
+    View::from_rect(FloatRect::new(0.0, 0.0, window.size().x as f32, window.size().y as f32))
+}
+
+// scaled to fit the window with black bars, everyone sees the same amount of the arena
+fn playfield_viewport(window: &RenderWindow) -> FloatRect {
+    let window_ratio = window.size().x as f32 / window.size().y.max(1) as f32;
+    let playfield_ratio = WINDOW_SIZE_X as f32 / WINDOW_SIZE_Y as f32;
+    if window_ratio > playfield_ratio {
+        let width = playfield_ratio / window_ratio;
+        FloatRect::new((1.0 - width) / 2.0, 0.0, width, 1.0)
+    } else {
+        let height = window_ratio / playfield_ratio;
+        FloatRect::new(0.0, (1.0 - height) / 2.0, 1.0, height)
+    }
+}
+
+// follows the player, stops at the arena edges, arenas smaller than the playfield just stay centered
+fn camera_view(window: &RenderWindow, game_state_clone: &GameState, map: &Map, player_id: u32) -> SfBox<View> {
+    let size = Vector2f::new(WINDOW_SIZE_X as f32, WINDOW_SIZE_Y as f32);
+    let (x, y) = match game_state_clone.players.iter().find(|p| p.id == player_id) {
+        Some(p) => (p.x, p.y),
+        None => (map.width / 2.0, map.height / 2.0)
+    };
+    let follow = |position: f32, view: f32, world: f32| {
+        if world <= view { world / 2.0 } else { position.clamp(view / 2.0, world - view / 2.0) }
+    };
+    let mut view = View::new(Vector2f::new(follow(x, size.x, map.width), follow(y, size.y, map.height)), size);
+    view.set_viewport(playfield_viewport(window));
+    view
+}
+
+fn draw_minimap(window: &mut RenderWindow, game_state_clone: &GameState, map: &Map, camera: &View, player_id: u32) {
+    if map.width <= 0.0 || map.height <= 0.0 {
+        return;
+    }
+
+    let margin = 0.02;
+    let width = 0.2;
+    let (screen_width, screen_height) = (window.size().x as f32, window.size().y.max(1) as f32);
+    let height = width * (map.height / map.width) * (screen_width / screen_height);
+    let mut minimap = View::from_rect(FloatRect::new(0.0, 0.0, map.width, map.height));
+    minimap.set_viewport(FloatRect::new(1.0 - width - margin, 1.0 - height - margin, width, height));
+    window.set_view(&minimap);
+
+    let pixel = map.width / (width * screen_width); // world units per minimap pixel
+    let mut background = RectangleShape::new();
+    background.set_size(Vector2f::new(map.width, map.height));
+    background.set_fill_color(Color::rgba(0, 0, 0, 180));
+    background.set_outline_color(Color::WHITE);
+    background.set_outline_thickness(-pixel);
+    window.draw(&background);
+
+    let mut tile = RectangleShape::new();
+    tile.set_size(Vector2f::new(BOX_SIZE, BOX_SIZE));
+    for wall in &map.walls {
+        tile.set_fill_color(obstacle_color(map.obstacle_type(wall)));
+        tile.set_position(Vector2f::new(wall.x, wall.y));
+        window.draw(&tile);
+    }
+    tile.set_fill_color(Color::YELLOW);
+    for box_item in &game_state_clone.boxes {
+        tile.set_position(Vector2f::new(box_item.x, box_item.y));
+        window.draw(&tile);
+    }
+
+    let mut dot = CircleShape::new(3.0 * pixel, 12);
+    for p in &game_state_clone.players {
+        dot.set_position(Vector2f::new(p.x - 3.0 * pixel, p.y - 3.0 * pixel));
+        dot.set_fill_color(if p.has_flag { Color::RED } else if p.id == player_id { Color::CYAN } else { Color::GREEN });
+        window.draw(&dot);
+    }
+    if game_state_clone.match_info.mode == GameMode::FlagHold && game_state_clone.flag_owner_id.is_none() {
+        dot.set_position(Vector2f::new(game_state_clone.flag_x - 3.0 * pixel, game_state_clone.flag_y - 3.0 * pixel));
+        dot.set_fill_color(Color::BLUE);
+        window.draw(&dot);
+    }
+
+    let mut visible_area = RectangleShape::new();
+    visible_area.set_size(camera.size());
+    visible_area.set_position(camera.center() - camera.size() / 2.0);
+    visible_area.set_fill_color(Color::TRANSPARENT);
+    visible_area.set_outline_color(Color::WHITE);
+    visible_area.set_outline_thickness(pixel);
+    window.draw(&visible_area);
+
+    window.set_view(&hud_view(window));
+}
@@ -0,0 +1,217 @@
+use crate::shared::*;
+use crate::pickup::*;
+
+// players, bullets, pickups and the flag sit at their centers,
+// walls and boxes are grid tiles positioned by their top left corner
+
+#[derive(Clone, Copy, Debug)]
+pub struct Circle {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+impl Circle {
+    pub fn overlaps(&self, other: &Circle) -> bool {
+        get_distance(self.x, other.x, self.y, other.y) < self.radius + other.radius
+    }
+
+    pub fn overlaps_rect(&self, rect: &Rect) -> bool {
+        let (x, y) = rect.closest_point(self.x, self.y);
+        get_distance(self.x, x, self.y, y) < self.radius
+    }
+}
+
+impl Rect {
+    pub fn tile(x: f32, y: f32) -> Self {
+        Rect { x, y, width: BOX_SIZE, height: BOX_SIZE }
+    }
+
+    pub fn square(center_x: f32, center_y: f32, size: f32) -> Self {
+        Rect { x: center_x - size / 2.0, y: center_y - size / 2.0, width: size, height: size }
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    pub fn closest_point(&self, x: f32, y: f32) -> (f32, f32) {
+        (x.clamp(self.x, self.x + self.width), y.clamp(self.y, self.y + self.height))
+    }
+
+    pub fn distance_to(&self, x: f32, y: f32) -> f32 {
+        let (closest_x, closest_y) = self.closest_point(x, y);
+        get_distance(x, closest_x, y, closest_y)
+    }
+
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x < other.x + other.width && other.x < self.x + self.width
+            && self.y < other.y + other.height && other.y < self.y + self.height
+    }
+}
+
+impl Player {
+    pub fn shape(&self) -> Circle {
+        Circle { x: self.x, y: self.y, radius: PLAYER_RADIUS }
+    }
+}
+
+impl WoodBox {
+    pub fn shape(&self) -> Rect {
+        Rect::tile(self.x, self.y)
+    }
+}
+
+impl Wall {
+    pub fn shape(&self) -> Rect {
+        Rect::tile(self.x, self.y)
+    }
+}
+
+impl Pickup {
+    pub fn shape(&self) -> Circle {
+        Circle { x: self.x, y: self.y, radius: PICKUP_SIZE / 2.0 }
+    }
+}
+
+impl GameState {
+    pub fn flag_shape(&self) -> Circle {
+        Circle { x: self.flag_x, y: self.flag_y, radius: FLAG_SIZE }
+    }
+}
+
+// mirrors a direction off the side of the rect the point touches
+pub fn reflect(direction: (f32, f32), rect: &Rect, (x, y): (f32, f32)) -> (f32, f32) {
+    let to_side_x = (x - rect.x).abs().min((x - rect.x - rect.width).abs());
+    let to_side_y = (y - rect.y).abs().min((y - rect.y - rect.height).abs());
+    if to_side_x < to_side_y {
+        (-direction.0, direction.1)
+    } else {
+        (direction.0, -direction.1)
+    }
+}
+
+// swept tests for things that move a lot per tick, the result is how far along
+// the from -> to segment (0.0 - 1.0) the first touch happens, None - no touch this tick
+
+pub fn segment_circle(from: (f32, f32), to: (f32, f32), circle: &Circle) -> Option<f32> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let (fx, fy) = (from.0 - circle.x, from.1 - circle.y);
+
+    let c = fx * fx + fy * fy - circle.radius * circle.radius;
+    if c <= 0.0 {
+        return Some(0.0); // starts inside
+    }
+
+    let a = dx * dx + dy * dy;
+    if a == 0.0 {
+        return None;
+    }
+    let b = 2.0 * (fx * dx + fy * dy);
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / (2.0 * a);
+    (0.0..=1.0).contains(&t).then_some(t)
+}
+
+// slab method
+pub fn segment_rect(from: (f32, f32), to: (f32, f32), rect: &Rect) -> Option<f32> {
+    let mut t_enter: f32 = 0.0;
+    let mut t_exit: f32 = 1.0;
+
+    let axes = [
+        (from.0, to.0 - from.0, rect.x, rect.x + rect.width),
+        (from.1, to.1 - from.1, rect.y, rect.y + rect.height)
+    ];
+    for (start, delta, low, high) in axes {
+        if delta == 0.0 {
+            if start < low || start > high {
+                return None;
+            }
+            continue;
+        }
+
+        let t1 = (low - start) / delta;
+        let t2 = (high - start) / delta;
+        t_enter = t_enter.max(t1.min(t2));
+        t_exit = t_exit.min(t1.max(t2));
+        if t_enter > t_exit {
+            return None;
+        }
+    }
+
+    Some(t_enter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapon::WeaponKind;
+
+    fn close(t: Option<f32>, expected: f32) -> bool {
+        t.is_some_and(|t| (t - expected).abs() < 1e-4)
+    }
+
+    // one tick of a rifle bullet, longer than a player or a box is wide
+    fn rifle_shot(from: (f32, f32)) -> ((f32, f32), (f32, f32)) {
+        let speed = WeaponKind::Rifle.stats().bullet_speed;
+        (from, (from.0 + speed, from.1))
+    }
+
+    #[test]
+    fn rifle_shot_through_player() {
+        let player = Circle { x: 100.0, y: 100.0, radius: PLAYER_RADIUS };
+        let (from, to) = rifle_shot((82.0, 100.0)); // both ends outside the circle
+        assert!(!player.overlaps(&Circle { x: from.0, y: from.1, radius: 0.0 }));
+        assert!(!player.overlaps(&Circle { x: to.0, y: to.1, radius: 0.0 }));
+        assert!(close(segment_circle(from, to, &player), 8.0 / 35.0));
+    }
+
+    #[test]
+    fn rifle_shot_through_box() {
+        let wood_box = Rect::tile(100.0, 100.0);
+        let (from, to) = rifle_shot((92.0, 110.0));
+        assert!(to.0 > wood_box.x + wood_box.width);
+        assert!(close(segment_rect(from, to, &wood_box), 8.0 / 35.0));
+    }
+
+    #[test]
+    fn starts_inside() {
+        let (from, to) = rifle_shot((100.0, 105.0));
+        assert_eq!(segment_circle(from, to, &Circle { x: 100.0, y: 100.0, radius: PLAYER_RADIUS }), Some(0.0));
+        let (from, to) = rifle_shot((110.0, 110.0));
+        assert_eq!(segment_rect(from, to, &Rect::tile(100.0, 100.0)), Some(0.0));
+    }
+
+    #[test]
+    fn misses() {
+        let player = Circle { x: 100.0, y: 100.0, radius: PLAYER_RADIUS };
+        let (from, to) = rifle_shot((82.0, 80.0)); // passes above
+        assert_eq!(segment_circle(from, to, &player), None);
+        let (from, to) = rifle_shot((40.0, 100.0)); // stops short
+        assert_eq!(segment_circle(from, to, &player), None);
+        let (from, to) = rifle_shot((92.0, 90.0));
+        assert_eq!(segment_rect(from, to, &Rect::tile(100.0, 100.0)), None);
+    }
+
+    #[test]
+    fn zero_length() {
+        let player = Circle { x: 100.0, y: 100.0, radius: PLAYER_RADIUS };
+        let wood_box = Rect::tile(100.0, 100.0);
+        assert_eq!(segment_circle((50.0, 50.0), (50.0, 50.0), &player), None);
+        assert_eq!(segment_circle((100.0, 100.0), (100.0, 100.0), &player), Some(0.0));
+        assert_eq!(segment_rect((50.0, 50.0), (50.0, 50.0), &wood_box), None);
+        assert_eq!(segment_rect((110.0, 110.0), (110.0, 110.0), &wood_box), Some(0.0));
+    }
+}
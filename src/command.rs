@@ -0,0 +1,71 @@
+use serde::Deserialize;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::shared::*;
+use crate::weapon::*;
+
+// everything a player can ask the server for, remote players and bots go through the same path
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum Command {
+    Input(MoveInput),
+    Shoot { dx: f32, dy: f32 },
+    Weapon(WeaponAction),
+    Vote(usize)
+}
+
+// who sent it, commands queue up here and the tick loop applies them in order
+pub type CommandSender = UnboundedSender<(u32, Command)>;
+pub type CommandReceiver = UnboundedReceiver<(u32, Command)>;
+
+// owner ids in the payload are ignored, a connection only ever speaks for its own player
+pub fn parse_command(buffer: &[u8]) -> Option<Command> {
+    let parsed = if let Some(payload) = buffer.strip_prefix(CMD_INPUT) {
+        serde_json::from_slice::<InputCommand>(payload).map(|c| Command::Input(c.input))
+    } else if let Some(payload) = buffer.strip_prefix(CMD_SHOOT) {
+        serde_json::from_slice::<Shot>(payload).map(|s| Command::Shoot { dx: s.dx, dy: s.dy })
+    } else if let Some(payload) = buffer.strip_prefix(CMD_WEAPON) {
+        serde_json::from_slice::<WeaponCommand>(payload).map(|c| Command::Weapon(c.action))
+    } else if let Some(payload) = buffer.strip_prefix(CMD_VOTE) {
+        serde_json::from_slice::<Vote>(payload).map(|v| Command::Vote(v.option))
+    } else {
+        return None;
+    };
+
+    match parsed {
+        Ok(command) => Some(command),
+        Err(x) => {
+            println!("{x} {}", String::from_utf8_lossy(buffer));
+            None
+        }
+    }
+}
+
+pub fn apply_command(game_state: &mut GameState, player_id: u32, command: Command) {
+    let phase = game_state.match_info.phase;
+    if let Command::Vote(option) = command {
+        let match_info = &mut game_state.match_info;
+        if phase == MatchPhase::PostMatch && option < match_info.vote_options.len() {
+            match_info.votes.insert(player_id, option);
+        }
+        return;
+    }
+
+    let Some(player) = game_state.players.iter_mut().find(|p| p.id == player_id) else { return };
+    match command {
+        Command::Input(input) => {
+            // a dash waits for the next tick even if another input comes in first
+            let dash = player.input.dash || input.dash;
+            player.input = MoveInput { dash, ..input };
+        },
+        Command::Shoot { dx, dy } => {
+            if phase != MatchPhase::Playing || !dx.is_finite() || !dy.is_finite() {
+                return;
+            }
+            let bullets = player.fire(dx, dy);
+            game_state.bullets.extend(bullets);
+        },
+        Command::Weapon(WeaponAction::Switch(kind)) => player.weapon = kind,
+        Command::Weapon(WeaponAction::Reload) => player.weapon_mut().start_reload(),
+        Command::Vote(_) => ()
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::Path;
+use serde::{Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
+
+use crate::shared::GameMode;
+use crate::bot::{BotSlot, Difficulty};
+
+pub const CONFIG_PATH: &str = "config.json";
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoxRespawn {
+    Original,
+    Random
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RotationEntry {
+    pub map: Option<String>, // None - built-in arena
+    pub mode: GameMode
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ServerConfig {
+    #[serde(deserialize_with = "rotation_or_default")]
+    pub rotation: Vec<RotationEntry>,
+    pub match_secs: u64,
+    pub score_limit: i32,
+    pub post_match_secs: u64,
+    pub map_voting: bool,
+    pub box_count: u32, // random boxes on top of the ones placed by the map
+    pub box_health: f32,
+    pub box_respawn_secs: u64,
+    pub box_respawn: BoxRespawn,
+    pub spawn_protection_secs: u64,
+    pub bots: Vec<BotSlot>, // always playing
+    pub min_players: usize, // bots fill in until there are this many players, leaving again as people join
+    pub fill_difficulty: Difficulty,
+    pub agent_port: Option<u16>, // None - no external bots
+    pub agent_budget_millis: u64 // how long an external bot gets to answer each tick
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            rotation: vec![RotationEntry::default()],
+            match_secs: 300,
+            score_limit: 100,
+            post_match_secs: 15,
+            map_voting: true,
+            box_count: 12,
+            box_health: 50.0,
+            box_respawn_secs: 10,
+            box_respawn: BoxRespawn::Original,
+            spawn_protection_secs: 2,
+            bots: vec![],
+            min_players: 2,
+            fill_difficulty: Difficulty::Normal,
+            agent_port: None,
+            agent_budget_millis: 20
+        }
+    }
+}
+
+// config.json, tournament.json, settings.json: a missing file means defaults, a broken one too but says why
+pub fn load_json_or_default<T: DeserializeOwned + Default>(path: &str) -> T {
+    let content = match std::fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(_) => return T::default()
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(value) => value,
+        Err(e) => {
+            println!("Invalid {path}, using defaults: {e}");
+            T::default()
+        }
+    }
+}
+
+// an empty rotation would leave nothing to play, the built-in arena stands in like for a missing one
+fn rotation_or_default<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<RotationEntry>, D::Error> {
+    let rotation = Vec::<RotationEntry>::deserialize(deserializer)?;
+    Ok(if rotation.is_empty() { vec![RotationEntry::default()] } else { rotation })
+}
+
+impl RotationEntry {
+    pub fn label(&self) -> String {
+        let map = self.map.as_deref()
+            .and_then(|path| Path::new(path).file_stem())
+            .and_then(|name| name.to_str())
+            .unwrap_or("arena");
+        format!("{map} - {:?}", self.mode)
+    }
+}
@@ -0,0 +1,409 @@
+use std::thread;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::shared::*;
+use crate::config::*;
+use crate::collision::*;
+use crate::command::*;
+use crate::bot::*;
+use crate::lobby::*;
+use crate::simulation::*;
+use crate::random::*;
+
+// the simulation as something to train agents in: reset, then step with every agent's commands for the tick
+// and get back what each of them sees, what it earned and whether the match is over; the same seed and the
+// same commands always play out the same way
+
+// what an agent gets for what happened during a tick
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct Rewards {
+    pub flag_hold: f32, // per second with the flag
+    pub kill: f32,
+    pub death: f32 // meant to be negative
+}
+
+impl Default for Rewards {
+    fn default() -> Self {
+        Rewards { flag_hold: 1.0, kill: 1.0, death: -1.0 }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Entities,
+    Grid,
+    Both
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct EnvConfig {
+    pub rules: ServerConfig, // every reset plays a random entry of the rotation, the bot settings are ignored
+    pub agents: usize, // players driven by step
+    pub opponents: Vec<BotSlot>, // built-in bots playing along
+    pub rewards: Rewards,
+    pub encoding: Encoding,
+    pub grid_cell: f32 // pixels per cell of the grid encoding
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            rules: ServerConfig::default(),
+            agents: 1,
+            opponents: vec![BotSlot::default()],
+            rewards: Rewards::default(),
+            encoding: Encoding::Entities,
+            grid_cell: BOX_SIZE
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityKind {
+    Me,
+    Enemy,
+    Bullet,
+    Box,
+    Pickup,
+    Flag
+}
+
+impl EntityKind {
+    pub const ALL: [EntityKind; 6] = [EntityKind::Me, EntityKind::Enemy, EntityKind::Bullet, EntityKind::Box, EntityKind::Pickup, EntityKind::Flag];
+}
+
+pub const ENTITY_FEATURES: usize = EntityKind::ALL.len() + 6;
+
+// one thing on the map as the observing player sees it
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub x: f32, // relative to the observing player, as a share of the map size
+    pub y: f32,
+    pub vx: f32, // pixels per tick
+    pub vy: f32,
+    pub health: f32, // 0.0 - 1.0 for players and boxes, 1.0 for the rest
+    pub has_flag: bool
+}
+
+impl Entity {
+    // kind one-hot, then the numbers, ready for a network
+    pub fn features(&self) -> [f32; ENTITY_FEATURES] {
+        let mut features = [0.0; ENTITY_FEATURES];
+        features[self.kind as usize] = 1.0;
+        let values = [self.x, self.y, self.vx, self.vy, self.health, if self.has_flag { 1.0 } else { 0.0 }];
+        features[EntityKind::ALL.len()..].copy_from_slice(&values);
+        features
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Walls,
+    Boxes,
+    Me,
+    Enemies,
+    Bullets,
+    Pickups,
+    Flag
+}
+
+pub const CHANNELS: usize = 7;
+
+// the whole map drawn into cells, one layer per channel, 1.0 where something of that channel is
+#[derive(Serialize, Clone, Debug)]
+pub struct Raster {
+    pub width: usize,
+    pub height: usize,
+    pub cell_size: f32,
+    pub cells: Vec<f32> // channel by channel, row by row
+}
+
+impl Raster {
+    fn new(map: &Map, cell_size: f32) -> Self {
+        let width = (map.width / cell_size).ceil() as usize;
+        let height = (map.height / cell_size).ceil() as usize;
+        Raster { width, height, cell_size, cells: vec![0.0; CHANNELS * width * height] }
+    }
+
+    pub fn get(&self, channel: Channel, x: usize, y: usize) -> f32 {
+        self.cells[(channel as usize * self.height + y) * self.width + x]
+    }
+
+    fn mark(&mut self, channel: Channel, x: f32, y: f32) {
+        let (x, y) = ((x / self.cell_size) as usize, (y / self.cell_size) as usize);
+        if x < self.width && y < self.height {
+            self.cells[(channel as usize * self.height + y) * self.width + x] = 1.0;
+        }
+    }
+
+    fn fill(&mut self, channel: Channel, rect: &Rect) {
+        let cell_size = self.cell_size;
+        let cells = |from: f32, size: f32| (from / cell_size) as usize..((from + size) / cell_size).ceil() as usize;
+        for y in cells(rect.y, rect.height) {
+            for x in cells(rect.x, rect.width) {
+                self.mark(channel, (x as f32 + 0.5) * cell_size, (y as f32 + 0.5) * cell_size);
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AgentObservation {
+    pub player_id: u32,
+    pub entities: Option<Vec<Entity>>, // the agent itself first
+    pub grid: Option<Raster>
+}
+
+pub struct Environment {
+    config: EnvConfig,
+    rules: ServerConfig,
+    rng: StdRng,
+    game_state: GameState,
+    simulation: SimulationState,
+    bots: BotManager,
+    agent_ids: Vec<u32>,
+    bot_commands: Vec<(u32, Command)>,
+    done: bool
+}
+
+impl Environment {
+    pub fn new(config: EnvConfig) -> Self {
+        let mut rules = config.rules.clone();
+        rules.bots = config.opponents.clone();
+        rules.min_players = 0;
+        Environment {
+            bots: BotManager::new(&rules),
+            config,
+            rules,
+            rng: StdRng::seed_from_u64(0),
+            game_state: GameState::default(),
+            simulation: SimulationState::default(),
+            agent_ids: vec![],
+            bot_commands: vec![],
+            done: true
+        }
+    }
+
+    pub fn game_state(&self) -> &GameState {
+        &self.game_state
+    }
+
+    pub fn agent_ids(&self) -> &[u32] {
+        &self.agent_ids
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Vec<AgentObservation> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        with_seeded(&mut rng, || self.start());
+        self.rng = rng;
+        self.observe()
+    }
+
+    // one tick, actions are the commands of each agent in the order of agent_ids;
+    // once the match is over nothing happens anymore until the next reset
+    pub fn step(&mut self, actions: &[Vec<Command>]) -> (Vec<AgentObservation>, Vec<f32>, bool) {
+        if self.done {
+            return (self.observe(), vec![0.0; self.agent_ids.len()], true);
+        }
+        let mut rng = self.rng.clone();
+        let rewards = with_seeded(&mut rng, || self.advance(actions));
+        self.rng = rng;
+        (self.observe(), rewards, self.done)
+    }
+
+    fn start(&mut self) {
+        let rotation_index = rng().gen_range(0..self.rules.rotation.len());
+        self.game_state = GameState::default();
+        self.simulation = SimulationState::default();
+        self.bots = BotManager::new(&self.rules);
+        start_match(&mut self.game_state, &self.rules, rotation_index);
+
+        // agents first so they get the same ids whatever the opponents
+        let ids = IdAllocator::new();
+        self.agent_ids = (0..self.config.agents)
+            .map(|_| {
+                let player_id = ids.allocate();
+                add_player(&mut self.game_state, player_id, &self.rules);
+                player_id
+            })
+            .collect();
+        self.bots.balance(&mut self.game_state, &ids, &self.rules);
+        self.bot_commands.clear();
+        self.done = false;
+    }
+
+    // the bots' commands from the last step go first, then the agents'
+    fn advance(&mut self, actions: &[Vec<Command>]) -> Vec<f32> {
+        let agent_commands = self.agent_ids.iter()
+            .zip(actions)
+            .flat_map(|(&player_id, commands)| commands.iter().map(move |&command| (player_id, command)));
+        let commands: Vec<(u32, Command)> = self.bot_commands.drain(..).chain(agent_commands).collect();
+        self.bot_commands = tick(&mut self.game_state, &mut self.simulation, &mut self.bots, commands, &self.rules);
+        self.done = self.game_state.match_info.phase == MatchPhase::PostMatch;
+
+        let weights = self.config.rewards;
+        let rewards = self.agent_ids.iter()
+            .map(|&id| {
+                let kills = self.simulation.kills.iter().filter(|(killer, _)| *killer == id).count() as f32;
+                let deaths = self.simulation.kills.iter().filter(|(_, victim)| *victim == id).count() as f32;
+                let holding = self.game_state.players.iter().any(|p| p.id == id && p.has_flag);
+                let flag = if holding { weights.flag_hold / TICKS_PER_SECOND as f32 } else { 0.0 };
+                kills * weights.kill + deaths * weights.death + flag
+            })
+            .collect();
+        rewards
+    }
+
+    fn observe(&self) -> Vec<AgentObservation> {
+        let encoding = self.config.encoding;
+        self.agent_ids.iter()
+            .map(|&player_id| AgentObservation {
+                player_id,
+                entities: (encoding != Encoding::Grid).then(|| entities(&self.game_state, player_id)),
+                grid: (encoding != Encoding::Entities).then(|| rasterize(&self.game_state, player_id, self.config.grid_cell))
+            })
+            .collect()
+    }
+
+    fn next_seed(&mut self) -> u64 {
+        self.rng.gen()
+    }
+}
+
+fn entities(game_state: &GameState, player_id: u32) -> Vec<Entity> {
+    let me = game_state.players.iter().find(|p| p.id == player_id);
+    let (origin_x, origin_y) = me.map_or((0.0, 0.0), |p| (p.x, p.y));
+    let (width, height) = (game_state.map.width.max(1.0), game_state.map.height.max(1.0));
+    let entity = |kind, x: f32, y: f32, (vx, vy), health, has_flag| Entity {
+        kind,
+        x: (x - origin_x) / width,
+        y: (y - origin_y) / height,
+        vx,
+        vy,
+        health,
+        has_flag
+    };
+
+    let players = me.into_iter().chain(game_state.players.iter().filter(|p| p.id != player_id))
+        .map(|p| {
+            let kind = if p.id == player_id { EntityKind::Me } else { EntityKind::Enemy };
+            entity(kind, p.x, p.y, (p.vx, p.vy), p.health / PLAYER_MAX_HEALTH, p.has_flag)
+        });
+    let bullets = game_state.bullets.iter().map(|b| {
+        let speed = b.weapon.stats().bullet_speed; // dx, dy is only the direction
+        entity(EntityKind::Bullet, b.x, b.y, (b.dx * speed, b.dy * speed), 1.0, false)
+    });
+    let boxes = game_state.boxes.iter().map(|b| entity(EntityKind::Box, b.x, b.y, (0.0, 0.0), b.health / b.max_health, false));
+    let pickups = game_state.pickups.iter().map(|p| entity(EntityKind::Pickup, p.x, p.y, (0.0, 0.0), 1.0, false));
+    let flag = (game_state.match_info.mode == GameMode::FlagHold)
+        .then(|| entity(EntityKind::Flag, game_state.flag_x, game_state.flag_y, (0.0, 0.0), 1.0, game_state.flag_owner_id.is_some()));
+    players.chain(bullets).chain(boxes).chain(pickups).chain(flag).collect()
+}
+
+fn rasterize(game_state: &GameState, player_id: u32, cell_size: f32) -> Raster {
+    let mut raster = Raster::new(&game_state.map, cell_size);
+    for wall in &game_state.map.walls {
+        raster.fill(Channel::Walls, &wall.shape());
+    }
+    for wood_box in &game_state.boxes {
+        raster.fill(Channel::Boxes, &wood_box.shape());
+    }
+    for player in &game_state.players {
+        let channel = if player.id == player_id { Channel::Me } else { Channel::Enemies };
+        raster.mark(channel, player.x, player.y);
+    }
+    for bullet in &game_state.bullets {
+        raster.mark(Channel::Bullets, bullet.x, bullet.y);
+    }
+    for pickup in &game_state.pickups {
+        raster.mark(Channel::Pickups, pickup.x, pickup.y);
+    }
+    if game_state.match_info.mode == GameMode::FlagHold {
+        raster.mark(Channel::Flag, game_state.flag_x, game_state.flag_y);
+    }
+    raster
+}
+
+// several environments stepped side by side across the cores, one that's over starts again right away
+// with a seed of its own, the observations it returns are already from the new match
+pub struct VecEnvironment {
+    envs: Vec<Environment>
+}
+
+impl VecEnvironment {
+    pub fn new(config: EnvConfig, count: usize) -> Self {
+        VecEnvironment { envs: (0..count).map(|_| Environment::new(config.clone())).collect() }
+    }
+
+    pub fn envs(&self) -> &[Environment] {
+        &self.envs
+    }
+
+    // environment i gets seed + i
+    pub fn reset(&mut self, seed: u64) -> Vec<Vec<AgentObservation>> {
+        self.run(|index, env| env.reset(seed.wrapping_add(index as u64)))
+    }
+
+    pub fn step(&mut self, actions: &[Vec<Vec<Command>>]) -> Vec<(Vec<AgentObservation>, Vec<f32>, bool)> {
+        self.run(|index, env| {
+            let (observations, rewards, done) = env.step(actions.get(index).map_or(&[], |a| a.as_slice()));
+            if !done {
+                return (observations, rewards, done);
+            }
+            let seed = env.next_seed();
+            (env.reset(seed), rewards, done)
+        })
+    }
+
+    fn run<T: Send>(&mut self, f: impl Fn(usize, &mut Environment) -> T + Sync) -> Vec<T> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = self.envs.len().div_ceil(threads).max(1);
+        let f = &f;
+        thread::scope(|scope| {
+            let handles: Vec<_> = self.envs.chunks_mut(chunk_size).enumerate()
+                .map(|(chunk, envs)| scope.spawn(move || {
+                    envs.iter_mut().enumerate().map(|(i, env)| f(chunk * chunk_size + i, env)).collect::<Vec<T>>()
+                }))
+                .collect();
+            handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every tick's state as the clients would get it, one agent running in circles and shooting
+    fn play(seed: u64, ticks: usize) -> Vec<String> {
+        let mut env = Environment::new(EnvConfig { agents: 1, opponents: vec![BotSlot::default(); 3], ..Default::default() });
+        env.reset(seed);
+        (0..ticks)
+            .map(|tick| {
+                let angle = tick as f32 * 0.05;
+                let actions = vec![vec![
+                    Command::Input(MoveInput { x: angle.cos(), y: angle.sin(), ..Default::default() }),
+                    Command::Shoot { dx: angle.sin(), dy: -angle.cos() }
+                ]];
+                env.step(&actions);
+                serde_json::to_string(env.game_state()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_plays_the_same() {
+        assert_eq!(play(7, 300), play(7, 300));
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        assert_ne!(play(7, 300), play(8, 300));
+    }
+}
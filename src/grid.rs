@@ -0,0 +1,131 @@
+use crate::shared::*;
+use crate::collision::*;
+use crate::pickup::*;
+
+pub const GRID_CELL_SIZE: f32 = 4.0 * BOX_SIZE;
+
+// index into the matching GameState vector, only valid until that vector changes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Entry {
+    Player(usize),
+    Box(usize),
+    Wall(usize),
+    Pickup(usize)
+}
+
+// uniform grid over the map, every entry sits in each cell its bounds touch so queries only look at nearby things,
+// anything past the edge of the map counts as being in the edge cell
+pub struct SpatialGrid {
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<Entry>>
+}
+
+impl Default for SpatialGrid {
+    fn default() -> Self {
+        SpatialGrid::new(GRID_CELL_SIZE, 0.0, 0.0)
+    }
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32, width: f32, height: f32) -> Self {
+        let columns = (width / cell_size).ceil().max(1.0) as usize;
+        let rows = (height / cell_size).ceil().max(1.0) as usize;
+        SpatialGrid { cell_size, columns, rows, cells: vec![vec![]; columns * rows] }
+    }
+
+    pub fn build(game_state: &GameState) -> Self {
+        let mut grid = SpatialGrid::new(GRID_CELL_SIZE, game_state.map.width, game_state.map.height);
+        grid.insert_players(&game_state.players);
+        grid.insert_boxes(&game_state.boxes);
+        for (index, w) in game_state.map.walls.iter().enumerate() {
+            grid.insert(Entry::Wall(index), &w.shape());
+        }
+        grid.insert_pickups(&game_state.pickups);
+        grid
+    }
+
+    pub fn insert(&mut self, entry: Entry, bounds: &Rect) {
+        for cell in self.cells_of(bounds) {
+            self.cells[cell].push(entry);
+        }
+    }
+
+    // once players moved, boxes broke or pickups got taken, cheaper than building it all again
+    pub fn refresh_players(&mut self, players: &[Player]) {
+        self.remove_all(|entry| matches!(entry, Entry::Player(_)));
+        self.insert_players(players);
+    }
+
+    pub fn refresh_boxes(&mut self, boxes: &[WoodBox]) {
+        self.remove_all(|entry| matches!(entry, Entry::Box(_)));
+        self.insert_boxes(boxes);
+    }
+
+    pub fn refresh_pickups(&mut self, pickups: &[Pickup]) {
+        self.remove_all(|entry| matches!(entry, Entry::Pickup(_)));
+        self.insert_pickups(pickups);
+    }
+
+    // everything that may touch the area, callers still do the exact test
+    pub fn query(&self, area: &Rect) -> Vec<Entry> {
+        let mut cells = self.cells_of(area);
+        // most queries are short bullet steps inside a single cell, nothing to dedup there
+        if let (Some(cell), None) = (cells.next(), cells.next()) {
+            return self.cells[cell].clone();
+        }
+        let mut found: Vec<Entry> = self.cells_of(area)
+            .flat_map(|cell| &self.cells[cell])
+            .copied()
+            .collect();
+        found.sort();
+        found.dedup();
+        found
+    }
+
+    pub fn query_segment(&self, from: (f32, f32), to: (f32, f32)) -> Vec<Entry> {
+        let (x, y) = (from.0.min(to.0), from.1.min(to.1));
+        self.query(&Rect { x, y, width: (from.0 - to.0).abs(), height: (from.1 - to.1).abs() })
+    }
+
+    pub fn query_circle(&self, circle: &Circle) -> Vec<Entry> {
+        self.query(&circle_bounds(circle))
+    }
+
+    fn insert_players(&mut self, players: &[Player]) {
+        for (index, p) in players.iter().enumerate() {
+            self.insert(Entry::Player(index), &circle_bounds(&p.shape()));
+        }
+    }
+
+    fn insert_boxes(&mut self, boxes: &[WoodBox]) {
+        for (index, b) in boxes.iter().enumerate() {
+            self.insert(Entry::Box(index), &b.shape());
+        }
+    }
+
+    fn insert_pickups(&mut self, pickups: &[Pickup]) {
+        for (index, p) in pickups.iter().enumerate() {
+            self.insert(Entry::Pickup(index), &circle_bounds(&p.shape()));
+        }
+    }
+
+    fn remove_all(&mut self, matching: impl Fn(&Entry) -> bool) {
+        for cell in &mut self.cells {
+            cell.retain(|entry| !matching(entry));
+        }
+    }
+
+    fn cells_of(&self, area: &Rect) -> impl Iterator<Item = usize> {
+        let cell = |value: f32, count: usize| ((value / self.cell_size).floor().max(0.0) as usize).min(count - 1);
+        let (min_x, min_y) = (cell(area.x, self.columns), cell(area.y, self.rows));
+        let (max_x, max_y) = (cell(area.x + area.width, self.columns), cell(area.y + area.height, self.rows));
+        let columns = self.columns;
+        (min_y..=max_y).flat_map(move |y| (min_x..=max_x).map(move |x| y * columns + x))
+    }
+}
+
+fn circle_bounds(circle: &Circle) -> Rect {
+    Rect::square(circle.x, circle.y, circle.radius * 2.0)
+}
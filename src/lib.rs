@@ -0,0 +1,23 @@
+// everything but the window: the server, the tournament runner and the benches build on this,
+// and so can anyone training agents against the simulation, see environment.rs
+
+pub mod shared;
+pub mod weapon;
+pub mod pickup;
+pub mod config;
+pub mod spawn;
+pub mod map;
+pub mod rotation;
+pub mod collision;
+pub mod grid;
+pub mod movement;
+pub mod command;
+pub mod navigation;
+pub mod behavior;
+pub mod bot;
+pub mod brain;
+pub mod agent;
+pub mod lobby;
+pub mod simulation;
+pub mod random;
+pub mod environment;
@@ -0,0 +1,151 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::shared::*;
+use crate::config::*;
+use crate::command::*;
+use crate::bot::*;
+use crate::navigation::*;
+use crate::grid::*;
+use crate::brain::*;
+
+// every player id comes from here, humans and bots alike, ids are never handed out twice
+pub struct IdAllocator {
+    next: AtomicU32
+}
+
+impl Default for IdAllocator {
+    fn default() -> Self {
+        IdAllocator::new()
+    }
+}
+
+impl IdAllocator {
+    pub fn new() -> Self {
+        IdAllocator { next: AtomicU32::new(1) }
+    }
+
+    pub fn allocate(&self) -> u32 {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+// typed into the server console
+#[derive(Clone, Copy, Debug)]
+pub enum BotRequest {
+    Add(BotSlot),
+    Remove,
+    Fill(usize),
+    List
+}
+
+impl BotRequest {
+    pub fn parse(line: &str) -> Option<Self> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["bot", "add", options @ ..] => {
+                // difficulty and brain in any order, whatever's left out is the default
+                let mut slot = BotSlot::default();
+                for option in options {
+                    match (Difficulty::parse(option), Brain::parse(option)) {
+                        (Some(difficulty), _) => slot.difficulty = difficulty,
+                        (_, Some(brain)) => slot.brain = brain,
+                        _ => return None
+                    }
+                }
+                Some(BotRequest::Add(slot))
+            },
+            ["bot", "remove"] => Some(BotRequest::Remove),
+            ["bot", "fill", count] => count.parse().ok().map(BotRequest::Fill),
+            ["bot", "list"] => Some(BotRequest::List),
+            _ => None
+        }
+    }
+}
+
+struct ActiveBot {
+    player_id: u32,
+    slot: BotSlot,
+    brain: Box<dyn BotBrain>
+}
+
+pub struct BotManager {
+    bots: Vec<ActiveBot>,
+    fixed: Vec<BotSlot>, // stay no matter how many people play
+    min_players: usize, // fill-ins top the game up to this many players and leave as people join
+    fill_difficulty: Difficulty,
+    navigation: NavGrid // shared by all bots, rebuilt whenever boxes or the map change
+}
+
+impl BotManager {
+    pub fn new(config: &ServerConfig) -> Self {
+        BotManager {
+            bots: vec![],
+            fixed: config.bots.clone(),
+            min_players: config.min_players,
+            fill_difficulty: config.fill_difficulty,
+            navigation: NavGrid::new()
+        }
+    }
+
+    pub fn handle(&mut self, request: BotRequest) {
+        match request {
+            BotRequest::Add(slot) => self.fixed.push(slot),
+            BotRequest::Remove => if self.fixed.pop().is_none() {
+                println!("No bots to remove, fill-ins keep the game at {} players", self.min_players);
+            },
+            BotRequest::Fill(count) => self.min_players = count,
+            BotRequest::List => {
+                let bots: Vec<(u32, Brain, Difficulty)> = self.bots.iter().map(|b| (b.player_id, b.slot.brain, b.slot.difficulty)).collect();
+                println!("Bots {bots:?}, {} fixed, filling up to {} players", self.fixed.len(), self.min_players);
+            }
+        }
+    }
+
+    pub fn is_bot(&self, player_id: u32) -> bool {
+        self.bots.iter().any(|b| b.player_id == player_id)
+    }
+
+    // fixed slots first, in the order they were configured
+    pub fn player_ids(&self) -> Vec<u32> {
+        self.bots.iter().map(|b| b.player_id).collect()
+    }
+
+    // adds or removes bots until there are as many as wanted for the people currently playing,
+    // the fixed ones come first so fill-ins are the ones leaving; quiet, training resets this all the time
+    pub fn balance(&mut self, game_state: &mut GameState, ids: &IdAllocator, config: &ServerConfig) {
+        let humans = game_state.players.iter().filter(|p| !self.is_bot(p.id)).count();
+        let wanted = self.fixed.len().max(self.min_players.saturating_sub(humans));
+        let fill = BotSlot { difficulty: self.fill_difficulty, ..Default::default() };
+        let slot = |index: usize| self.fixed.get(index).copied().unwrap_or(fill);
+
+        while self.bots.len() < wanted {
+            let player_id = ids.allocate();
+            crate::simulation::add_player(game_state, player_id, config);
+            let slot = slot(self.bots.len());
+            self.bots.push(ActiveBot { player_id, slot, brain: slot.brain.create(slot) });
+        }
+        while self.bots.len() > wanted {
+            let Some(bot) = self.bots.pop() else { break };
+            game_state.remove_player(bot.player_id);
+        }
+        // a fill-in can become one of the fixed bots, it gets that slot's brain from then on
+        for (index, bot) in self.bots.iter_mut().enumerate() {
+            if bot.slot != slot(index) {
+                bot.slot = slot(index);
+                bot.brain = bot.slot.brain.create(bot.slot);
+            }
+        }
+    }
+
+    pub fn update(&mut self, game_state: &GameState, grid: &SpatialGrid) -> Vec<(u32, Command)> {
+        self.navigation.update(game_state);
+        let navigation = &self.navigation;
+        self.bots.iter_mut()
+            .flat_map(|bot| {
+                let player_id = bot.player_id;
+                let observation = Observation { player_id, game_state, navigation, grid };
+                bot.brain.think(&observation).into_iter().map(move |command| (player_id, command))
+            })
+            .collect()
+    }
+}
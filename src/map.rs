@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+
+use crate::shared::*;
+use crate::collision::Rect;
+
+const DEFAULT_MAP: &str = include_str!("../maps/arena.json");
+
+const RESERVED_TILES: [char; 6] = ['B', 'F', 'S', 'P', '.', ' '];
+
+// one character per BOX_SIZE tile:
+// '#' wall, 'B' box, 'F' flag, 'S' spawn point, 'P' pickup spot, '.' or ' ' nothing,
+// any other character declared in obstacles is a wall that treats bullets its own way
+#[derive(Deserialize)]
+struct MapFile {
+    name: String,
+    tiles: Vec<String>,
+    #[serde(default)]
+    obstacles: HashMap<char, ObstacleType>
+}
+
+pub fn load_map(path: Option<&str>) -> Map {
+    let path = match path {
+        Some(x) => x,
+        None => return parse_map(DEFAULT_MAP).unwrap()
+    };
+
+    let result = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| parse_map(&content));
+    match result {
+        Ok(map) => map,
+        Err(e) => {
+            println!("Failed to load map {path}, using the built-in one: {e}");
+            parse_map(DEFAULT_MAP).unwrap()
+        }
+    }
+}
+
+pub fn parse_map(content: &str) -> Result<Map, String> {
+    let file = serde_json::from_str::<MapFile>(content).map_err(|e| e.to_string())?;
+    let columns = file.tiles.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+    if columns == 0 {
+        return Err(String::from("map has no tiles"));
+    }
+    if columns < 2 || file.tiles.len() < 2 {
+        return Err(format!("map is {columns}x{} tiles, it needs at least 2x2", file.tiles.len()));
+    }
+
+    let width = columns as f32 * BOX_SIZE;
+    let height = file.tiles.len() as f32 * BOX_SIZE;
+    let mut map = Map {
+        name: file.name,
+        width,
+        height,
+        flag: (width / 2.0, height / 2.0),
+        ..Default::default()
+    };
+
+    let mut obstacles = file.obstacles;
+    if let Some(tile) = obstacles.keys().find(|t| RESERVED_TILES.contains(t)) {
+        return Err(format!("'{tile}' can't be an obstacle"));
+    }
+    obstacles.entry('#').or_insert(ObstacleType { bullets: BulletBehavior::Ricochet, ..Default::default() });
+    let mut kinds = HashMap::new();
+    for (tile, obstacle) in obstacles {
+        kinds.insert(tile, map.obstacle_types.len());
+        map.obstacle_types.push(obstacle);
+    }
+
+    let mut has_flag = false;
+    for (row, line) in file.tiles.iter().enumerate() {
+        for (column, tile) in line.chars().enumerate() {
+            let (x, y) = (column as f32 * BOX_SIZE, row as f32 * BOX_SIZE);
+            let center = Rect::tile(x, y).center(); // things standing on a tile are placed by their center
+            match tile {
+                'B' => map.boxes.push((x, y)),
+                'S' => map.spawn_points.push(SpawnPoint { x: center.0, y: center.1 }),
+                'P' => map.pickup_spots.push(center),
+                'F' if has_flag => return Err(format!("second flag at {column},{row}")),
+                'F' => {
+                    map.flag = center;
+                    has_flag = true;
+                },
+                '.' | ' ' => (),
+                other => match kinds.get(&other) {
+                    Some(&kind) => map.walls.push(Wall { x, y, kind }),
+                    None => return Err(format!("unknown tile '{other}' at {column},{row}"))
+                }
+            }
+        }
+    }
+
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_maps_under_two_tiles() {
+        assert!(parse_map(r#"{ "name": "tiny", "tiles": ["S", "F"] }"#).is_err());
+        assert!(parse_map(r#"{ "name": "tiny", "tiles": ["SF"] }"#).is_err());
+        assert!(parse_map(r#"{ "name": "tiny", "tiles": ["S.", ".F"] }"#).is_ok());
+    }
+}
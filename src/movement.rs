@@ -0,0 +1,115 @@
+use crate::shared::*;
+use crate::collision::*;
+
+// per tick, at 24 ticks a second
+pub const PLAYER_ACCELERATION: f32 = 2.5;
+pub const PLAYER_FRICTION: f32 = 0.2; // share of the velocity lost every tick
+pub const PLAYER_MAX_SPEED: f32 = 10.0;
+pub const FLAG_CARRIER_SPEED_MULTIPLIER: f32 = 0.75;
+pub const SPRINT_SPEED_MULTIPLIER: f32 = 1.5;
+pub const SPRINT_STAMINA_DRAIN: f32 = 1.5;
+pub const STAMINA_REGEN: f32 = 0.75;
+pub const DASH_SPEED: f32 = 30.0;
+pub const DASH_TICKS: u32 = 4; // how long the speed limit doesn't apply after a dash
+
+impl MoveInput {
+    // diagonals are as fast as straight lines, analog input below full tilt stays as is,
+    // NaN or infinity from a client would end up in every position sent out, so that's no input at all
+    pub fn direction(&self) -> (f32, f32) {
+        if !self.x.is_finite() || !self.y.is_finite() {
+            return (0.0, 0.0);
+        }
+        let (x, y) = (self.x.clamp(-1.0, 1.0), self.y.clamp(-1.0, 1.0));
+        let length = (x * x + y * y).sqrt();
+        if length > 1.0 {
+            (x / length, y / length)
+        } else {
+            (x, y)
+        }
+    }
+}
+
+impl Player {
+    pub fn max_speed(&self) -> f32 {
+        let flag = if self.has_flag { FLAG_CARRIER_SPEED_MULTIPLIER } else { 1.0 };
+        PLAYER_MAX_SPEED * self.speed_multiplier() * flag
+    }
+
+    // accelerates towards the wanted direction, friction slows it down when nothing is pressed
+    pub fn step(&mut self, obstacles: &[Rect], bounds: (f32, f32)) {
+        let (ax, ay) = self.input.direction();
+        let moving = ax != 0.0 || ay != 0.0;
+
+        let sprinting = self.input.sprint && moving && self.stamina > 0.0;
+        if sprinting {
+            self.stamina = (self.stamina - SPRINT_STAMINA_DRAIN).max(0.0);
+        } else {
+            self.stamina = (self.stamina + STAMINA_REGEN).min(PLAYER_MAX_STAMINA);
+        }
+
+        self.dash_cooldown = self.dash_cooldown.saturating_sub(1);
+        if self.input.dash {
+            self.input.dash = false;
+            self.dash();
+        }
+        let dashing = self.dash_cooldown > DASH_COOLDOWN_TICKS - DASH_TICKS;
+
+        let sprint = if sprinting { SPRINT_SPEED_MULTIPLIER } else { 1.0 };
+        let max_speed = self.max_speed() * sprint;
+        let acceleration = PLAYER_ACCELERATION * self.speed_multiplier() * sprint;
+        self.vx = self.vx * (1.0 - PLAYER_FRICTION) + ax * acceleration;
+        self.vy = self.vy * (1.0 - PLAYER_FRICTION) + ay * acceleration;
+
+        let speed = (self.vx * self.vx + self.vy * self.vy).sqrt();
+        if speed > max_speed && !dashing {
+            self.vx *= max_speed / speed;
+            self.vy *= max_speed / speed;
+        } else if speed < 0.1 {
+            (self.vx, self.vy) = (0.0, 0.0);
+        }
+
+        let (x, y) = slide(self.shape(), self.vx, self.vy, obstacles, bounds);
+        // bumping into something kills the speed along that axis
+        if x == self.x {
+            self.vx = 0.0;
+        }
+        if y == self.y {
+            self.vy = 0.0;
+        }
+        (self.x, self.y) = (x, y);
+    }
+
+    // burst towards where the player is heading, or keeps going the same way if nothing is pressed
+    fn dash(&mut self) {
+        if self.dash_cooldown > 0 {
+            return;
+        }
+
+        let (ax, ay) = self.input.direction();
+        let (dx, dy) = if ax != 0.0 || ay != 0.0 { normalize((ax, ay)) } else { normalize((self.vx, self.vy)) };
+        if dx == 0.0 && dy == 0.0 {
+            return;
+        }
+        self.vx = dx * DASH_SPEED;
+        self.vy = dy * DASH_SPEED;
+        self.dash_cooldown = DASH_COOLDOWN_TICKS;
+    }
+}
+
+// one axis at a time so whoever bumps into a wall slides along it instead of getting stuck
+pub fn slide(shape: Circle, dx: f32, dy: f32, obstacles: &[Rect], (width, height): (f32, f32)) -> (f32, f32) {
+    let blocked = |x: f32, y: f32| {
+        let moved = Circle { x, y, ..shape };
+        obstacles.iter().any(|o| moved.overlaps_rect(o))
+    };
+
+    let mut x = (shape.x + dx).clamp(shape.radius, width - shape.radius);
+    if blocked(x, shape.y) {
+        x = shape.x;
+    }
+    let mut y = (shape.y + dy).clamp(shape.radius, height - shape.radius);
+    if blocked(x, y) {
+        y = shape.y;
+    }
+    (x, y)
+}
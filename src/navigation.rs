@@ -0,0 +1,218 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use rand::Rng;
+
+use crate::shared::*;
+use crate::collision::*;
+use crate::random::*;
+
+pub const NAV_CELL_SIZE: f32 = BOX_SIZE;
+
+// costs of a straight and a diagonal step, integers keep the heap ordering simple
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+type Cell = (i32, i32);
+
+// walkable tiles of the map, a cell is blocked when a player standing in its middle would touch a wall or a box
+#[derive(Default)]
+pub struct NavGrid {
+    pub version: u64, // goes up with every rebuild so bots know their paths are outdated
+    width: i32,
+    height: i32,
+    blocked: Vec<bool>,
+    solid: Vec<bool>, // some wall or box covers part of the cell
+    built_for: (String, Vec<(f32, f32)>) // map name and box positions the grid was built from
+}
+
+impl NavGrid {
+    pub fn new() -> Self {
+        NavGrid::default()
+    }
+
+    // boxes get destroyed, respawn somewhere else and the map changes between matches
+    pub fn update(&mut self, game_state: &GameState) {
+        let boxes: Vec<(f32, f32)> = game_state.boxes.iter().map(|b| (b.x, b.y)).collect();
+        if self.built_for.0 == game_state.map.name && self.built_for.1 == boxes {
+            return;
+        }
+
+        self.width = (game_state.map.width / NAV_CELL_SIZE).ceil() as i32;
+        self.height = (game_state.map.height / NAV_CELL_SIZE).ceil() as i32;
+        let obstacles: Vec<Rect> = game_state.map.walls.iter().map(|w| w.shape())
+            .chain(game_state.boxes.iter().map(|b| b.shape()))
+            .collect();
+        self.blocked = vec![false; (self.width * self.height) as usize];
+        self.solid = vec![false; (self.width * self.height) as usize];
+        for obstacle in &obstacles {
+            self.mark_solid(obstacle);
+            let first = cell_of(obstacle.x - PLAYER_RADIUS, obstacle.y - PLAYER_RADIUS);
+            let last = cell_of(obstacle.x + obstacle.width + PLAYER_RADIUS, obstacle.y + obstacle.height + PLAYER_RADIUS);
+            for x in first.0.max(0)..=last.0.min(self.width - 1) {
+                for y in first.1.max(0)..=last.1.min(self.height - 1) {
+                    let (center_x, center_y) = center_of((x, y));
+                    if (Circle { x: center_x, y: center_y, radius: PLAYER_RADIUS }).overlaps_rect(obstacle) {
+                        self.blocked[(y * self.width + x) as usize] = true;
+                    }
+                }
+            }
+        }
+
+        self.built_for = (game_state.map.name.clone(), boxes);
+        self.version += 1;
+    }
+
+    fn mark_solid(&mut self, area: &Rect) {
+        let first = cell_of(area.x, area.y);
+        let last = cell_of(area.x + area.width - 0.01, area.y + area.height - 0.01);
+        for x in first.0.max(0)..=last.0.min(self.width - 1) {
+            for y in first.1.max(0)..=last.1.min(self.height - 1) {
+                self.solid[(y * self.width + x) as usize] = true;
+            }
+        }
+    }
+
+    // a bit stricter than the real thing, the player counts as a square
+    fn fits(&self, x: f32, y: f32) -> bool {
+        let reach = PLAYER_RADIUS - 0.5;
+        let first = cell_of(x - reach, y - reach);
+        let last = cell_of(x + reach, y + reach);
+        (first.0..=last.0).all(|cx| (first.1..=last.1).all(|cy| {
+            self.contains((cx, cy)) && !self.solid[(cy * self.width + cx) as usize]
+        }))
+    }
+
+    fn contains(&self, (x, y): Cell) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    pub fn is_walkable(&self, cell: Cell) -> bool {
+        self.contains(cell) && !self.blocked[(cell.1 * self.width + cell.0) as usize]
+    }
+
+    pub fn is_free(&self, x: f32, y: f32) -> bool {
+        self.is_walkable(cell_of(x, y))
+    }
+
+    pub fn random_point(&self) -> (f32, f32) {
+        let mut rng = rng();
+        for _ in 0..100 {
+            let cell = (rng.gen_range(0..self.width.max(1)), rng.gen_range(0..self.height.max(1)));
+            if self.is_walkable(cell) {
+                return center_of(cell);
+            }
+        }
+        center_of((self.width / 2, self.height / 2))
+    }
+
+    // A* over the cells, then smoothed so bots walk straight wherever nothing is in the way,
+    // the result are the points to walk through, None - no way there
+    pub fn find_path(&self, from: (f32, f32), to: (f32, f32)) -> Option<Vec<(f32, f32)>> {
+        let start = cell_of(from.0, from.1);
+        let goal = self.nearest_walkable(cell_of(to.0, to.1))?;
+        if !self.contains(start) {
+            return None;
+        }
+
+        let index = |(x, y): Cell| (y * self.width + x) as usize;
+        let mut open = BinaryHeap::new();
+        let mut came_from: Vec<Option<Cell>> = vec![None; self.blocked.len()];
+        let mut cost = vec![u32::MAX; self.blocked.len()];
+        cost[index(start)] = 0;
+        open.push(Reverse((heuristic(start, goal), start)));
+
+        while let Some(Reverse((estimate, cell))) = open.pop() {
+            if cell == goal {
+                let mut cells = vec![goal];
+                while let Some(previous) = came_from[index(*cells.last().unwrap())] {
+                    cells.push(previous);
+                }
+                cells.reverse();
+                return Some(self.smooth(from, &cells, to));
+            }
+            if estimate > cost[index(cell)] + heuristic(cell, goal) {
+                continue; // a cheaper way here was already looked at
+            }
+
+            for (next, step) in self.neighbours(cell) {
+                let next_cost = cost[index(cell)] + step;
+                if next_cost < cost[index(next)] {
+                    cost[index(next)] = next_cost;
+                    came_from[index(next)] = Some(cell);
+                    open.push(Reverse((next_cost + heuristic(next, goal), next)));
+                }
+            }
+        }
+        None
+    }
+
+    // diagonals only when both sides are free, otherwise players would clip the corner
+    fn neighbours(&self, (x, y): Cell) -> impl Iterator<Item = (Cell, u32)> + '_ {
+        [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)].into_iter()
+            .filter(move |&(dx, dy)| {
+                self.is_walkable((x + dx, y + dy))
+                    && (dx == 0 || dy == 0 || (self.is_walkable((x + dx, y)) && self.is_walkable((x, y + dy))))
+            })
+            .map(move |(dx, dy)| ((x + dx, y + dy), if dx == 0 || dy == 0 { STRAIGHT_COST } else { DIAGONAL_COST }))
+    }
+
+    // targets inside a wall or a box are swapped for the closest free cell around them
+    fn nearest_walkable(&self, (x, y): Cell) -> Option<Cell> {
+        (0..8).find_map(|ring| {
+            (-ring..=ring)
+                .flat_map(|dx| (-ring..=ring).map(move |dy| (x + dx, y + dy)))
+                .filter(|&(cx, cy)| (cx - x).abs() == ring || (cy - y).abs() == ring)
+                .find(|&cell| self.is_walkable(cell))
+        })
+    }
+
+    // string pulling, skips every point that can be reached in a straight line from the last kept one
+    fn smooth(&self, from: (f32, f32), cells: &[Cell], to: (f32, f32)) -> Vec<(f32, f32)> {
+        let goal = if self.is_walkable(cell_of(to.0, to.1)) { to } else { center_of(cells[cells.len() - 1]) };
+        // the first cell is where the bot already stands
+        let mut points: Vec<(f32, f32)> = cells[1..].iter().map(|&c| center_of(c)).collect();
+        match points.last_mut() {
+            Some(last) => *last = goal,
+            None => points.push(goal)
+        }
+
+        let mut path = vec![];
+        let mut anchor = from;
+        let mut index = 0;
+        while index < points.len() {
+            let mut furthest = index;
+            while furthest + 1 < points.len() && self.is_clear(anchor, points[furthest + 1]) {
+                furthest += 1;
+            }
+            anchor = points[furthest];
+            path.push(anchor);
+            index = furthest + 1;
+        }
+        path
+    }
+
+    // the whole body has to get through, not just the middle of it
+    fn is_clear(&self, from: (f32, f32), to: (f32, f32)) -> bool {
+        let steps = (get_distance(from.0, to.0, from.1, to.1) / (NAV_CELL_SIZE / 4.0)).ceil().max(1.0) as i32;
+        (0..=steps).all(|step| {
+            let t = step as f32 / steps as f32;
+            self.fits(from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+        })
+    }
+}
+
+fn cell_of(x: f32, y: f32) -> Cell {
+    ((x / NAV_CELL_SIZE).floor() as i32, (y / NAV_CELL_SIZE).floor() as i32)
+}
+
+fn center_of((x, y): Cell) -> (f32, f32) {
+    ((x as f32 + 0.5) * NAV_CELL_SIZE, (y as f32 + 0.5) * NAV_CELL_SIZE)
+}
+
+// octile distance, exact on an empty grid
+fn heuristic(from: Cell, to: Cell) -> u32 {
+    let dx = (from.0 - to.0).unsigned_abs();
+    let dy = (from.1 - to.1).unsigned_abs();
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+use crate::shared::TICKS_PER_SECOND;
+
+pub const PICKUP_SIZE: f32 = 12.0;
+pub const PICKUP_SPAWN_TICKS: u64 = 8 * TICKS_PER_SECOND;
+pub const MAX_PICKUPS: usize = 4;
+pub const SPEED_BOOST_MULTIPLIER: f32 = 1.6;
+pub const HEALTH_PACK_HEAL: f32 = 50.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickupKind {
+    SpeedBoost,
+    Shield,
+    RapidFire,
+    HealthPack
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Pickup {
+    pub x: f32,
+    pub y: f32,
+    pub kind: PickupKind
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Effect {
+    pub kind: PickupKind,
+    pub ticks_left: u32
+}
+
+impl PickupKind {
+    pub const ALL: [PickupKind; 4] = [PickupKind::SpeedBoost, PickupKind::Shield, PickupKind::RapidFire, PickupKind::HealthPack];
+
+    // 0 - applied instantly, nothing to track
+    pub fn duration_ticks(self) -> u32 {
+        let seconds = match self {
+            PickupKind::SpeedBoost => 6,
+            PickupKind::Shield => 5,
+            PickupKind::RapidFire => 8,
+            PickupKind::HealthPack => 0
+        };
+        seconds * TICKS_PER_SECOND as u32
+    }
+}
@@ -0,0 +1,50 @@
+use std::cell::RefCell;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+// where the simulation and the bots get their randomness from: the thread's own generator,
+// or a seeded one while something has to play out the same way every time
+
+thread_local! {
+    static SEEDED: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+pub struct SimRng;
+
+pub fn rng() -> SimRng {
+    SimRng
+}
+
+// everything f draws comes from rng, which carries on from there the next time
+pub fn with_seeded<T>(rng: &mut StdRng, f: impl FnOnce() -> T) -> T {
+    let seeded = std::mem::replace(rng, StdRng::seed_from_u64(0));
+    let previous = SEEDED.with(|s| s.replace(Some(seeded)));
+    let result = f();
+    *rng = SEEDED.with(|s| s.replace(previous)).unwrap();
+    result
+}
+
+fn draw<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    SEEDED.with(|s| match s.borrow_mut().as_mut() {
+        Some(seeded) => f(seeded),
+        None => f(&mut rand::thread_rng())
+    })
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        draw(|r| r.next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        draw(|r| r.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        draw(|r| r.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        draw(|r| r.try_fill_bytes(dest))
+    }
+}
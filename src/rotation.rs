@@ -0,0 +1,44 @@
+use crate::shared::*;
+use crate::config::ServerConfig;
+
+// ends the match on time or once someone reaches the score limit, opens the vote for the next one
+pub fn update_match(game_state: &mut GameState, config: &ServerConfig) {
+    let GameState { players, match_info, .. } = game_state;
+    match_info.ticks_left = match_info.ticks_left.saturating_sub(1);
+
+    let leader = players.iter().max_by_key(|p| p.score);
+    let score_reached = leader.is_some_and(|p| p.score >= match_info.score_limit);
+    if match_info.ticks_left > 0 && !score_reached {
+        return;
+    }
+
+    match_info.phase = MatchPhase::PostMatch;
+    match_info.winner_id = leader.map(|p| p.id);
+    match_info.ticks_left = config.post_match_secs * TICKS_PER_SECOND;
+    match_info.votes.clear();
+    match_info.vote_options = if config.map_voting {
+        config.rotation.iter().map(|entry| entry.label()).collect()
+    } else {
+        vec![]
+    };
+}
+
+// most voted option, ties and no votes at all go to whichever comes first after the current one
+pub fn next_rotation_index(match_info: &MatchInfo, current: usize, config: &ServerConfig) -> usize {
+    let count = config.rotation.len();
+    let mut tally = vec![0; count];
+    for option in match_info.votes.values() {
+        if *option < count {
+            tally[*option] += 1;
+        }
+    }
+
+    let mut best = (current + 1) % count;
+    for step in 1..=count {
+        let index = (current + step) % count;
+        if tally[index] > tally[best] {
+            best = index;
+        }
+    }
+    best
+}
@@ -0,0 +1,25 @@
+use serde::Deserialize;
+
+pub(crate) const SETTINGS_PATH: &str = "settings.json";
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ClientSettings {
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool, // desktop resolution, width and height are ignored
+    pub resizable: bool,
+    pub vsync: bool
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        ClientSettings {
+            width: 800,
+            height: 600,
+            fullscreen: false,
+            resizable: true,
+            vsync: true
+        }
+    }
+}
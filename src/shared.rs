@@ -1,24 +1,122 @@
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
-use rand::Rng;
-use tokio::{io::AsyncWriteExt, net::tcp::OwnedWriteHalf};
-
-pub(crate) const WINDOW_SIZE_X: u32 = 800;
-pub(crate) const WINDOW_SIZE_Y: u32 = 600;
-pub(crate) const PLAYER_RADIUS: f32 = 20.0;
-pub(crate) const BOX_SIZE: f32 = 20.0;
-pub(crate) const FLAG_SIZE: f32 = 5.0;
-pub(crate) const CMD_PLAYER: &[u8] = b"PLAYER";
-pub(crate) const CMD_BULLET: &[u8] = b"BULLET";
-pub(crate) const CMD_SEPARATOR: &[u8] = b":D/";
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+use crate::weapon::*;
+use crate::pickup::*;
+
+pub const WINDOW_SIZE_X: u32 = 800;
+pub const WINDOW_SIZE_Y: u32 = 600;
+pub const PLAYER_RADIUS: f32 = 10.0;
+pub const BOX_SIZE: f32 = 20.0;
+pub const FLAG_SIZE: f32 = 5.0;
+pub const PLAYER_MAX_HEALTH: f32 = 100.0;
+pub const PLAYER_MAX_ARMOR: f32 = 100.0;
+pub const PLAYER_SPAWN_ARMOR: f32 = 50.0;
+pub const ARMOR_ABSORPTION: f32 = 0.5;
+pub const PLAYER_MAX_STAMINA: f32 = 100.0;
+pub const DASH_COOLDOWN_TICKS: u32 = 2 * TICKS_PER_SECOND as u32;
+pub const TICKS_PER_SECOND: u64 = 24;
+pub const EVENT_TICKS: u32 = 8;
+pub const CMD_INPUT: &[u8] = b"INPUT";
+pub const CMD_SHOOT: &[u8] = b"SHOOT";
+pub const CMD_WEAPON: &[u8] = b"WEAPON";
+pub const CMD_STATE: &[u8] = b"STATE";
+pub const CMD_MAP: &[u8] = b"MAP";
+pub const CMD_VOTE: &[u8] = b"VOTE";
+pub const CMD_SEPARATOR: &[u8] = b":D/";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct GameState {
     pub players: Vec<Player>,
     pub flag_x: f32,
     pub flag_y: f32,
     pub flag_owner_id: Option<u32>,
     pub bullets: Vec<Bullet>,
-    pub boxes: Vec<WoodBox>
+    pub boxes: Vec<WoodBox>,
+    pub events: Vec<GameEvent>,
+    pub pickups: Vec<Pickup>,
+    pub match_info: MatchInfo,
+    #[serde(skip)] // static, clients get it once on join
+    pub map: Map
+}
+
+impl GameState {
+    // the flag stays where its carrier went down or left, free for anyone to pick up
+    pub fn drop_flag(&mut self, player_id: u32) {
+        if self.flag_owner_id == Some(player_id) {
+            self.flag_owner_id = None;
+        }
+        if let Some(player) = self.players.iter_mut().find(|p| p.id == player_id) {
+            player.has_flag = false;
+        }
+    }
+
+    pub fn remove_player(&mut self, player_id: u32) {
+        self.drop_flag(player_id);
+        self.players.retain(|p| p.id != player_id);
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GameMode {
+    #[default]
+    FlagHold,
+    Deathmatch
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MatchPhase {
+    #[default]
+    Playing,
+    PostMatch
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MatchInfo {
+    pub phase: MatchPhase,
+    pub mode: GameMode,
+    pub ticks_left: u64, // till the end of the match or, after it, till the next one starts
+    pub score_limit: i32,
+    pub winner_id: Option<u32>,
+    pub vote_options: Vec<String>,
+    pub votes: HashMap<u32, usize> // voter id -> option index
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Map {
+    pub name: String,
+    pub width: f32,
+    pub height: f32,
+    pub walls: Vec<Wall>,
+    pub boxes: Vec<(f32, f32)>,
+    pub flag: (f32, f32),
+    pub spawn_points: Vec<SpawnPoint>,
+    pub pickup_spots: Vec<(f32, f32)>,
+    pub obstacle_types: Vec<ObstacleType>
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BulletBehavior {
+    #[default]
+    Block,
+    Ricochet, // bullets with bounces left fly off, the rest stop
+    Pass
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ObstacleType {
+    pub bullets: BulletBehavior,
+    pub color: (u8, u8, u8)
+}
+
+impl Default for ObstacleType {
+    fn default() -> Self {
+        ObstacleType {
+            bullets: BulletBehavior::Block,
+            color: (120, 120, 120)
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -28,7 +126,19 @@ pub struct Player {
     pub y: f32,
     pub has_flag: bool,
     pub respawn_num: u32,
-    pub score: i32
+    pub score: i32,
+    pub health: f32,
+    pub armor: f32,
+    pub weapon: WeaponKind,
+    pub weapons: Vec<Weapon>,
+    pub effects: Vec<Effect>,
+    pub spawn_protection: u32,
+    pub vx: f32,
+    pub vy: f32,
+    pub stamina: f32,
+    pub dash_cooldown: u32,
+    #[serde(skip)] // only the server moves players
+    pub input: MoveInput
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -38,29 +148,239 @@ pub struct Bullet {
     pub dx: f32,
     pub dy: f32,
     pub owner_id: u32,
+    pub damage: f32,
+    pub weapon: WeaponKind,
+    pub ticks: u32,
+    pub bounces: u32
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SpawnPoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Vote {
+    pub option: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Shot {
+    pub dx: f32,
+    pub dy: f32,
+}
+
+// where the player wants to go, the server does the actual moving every tick
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct MoveInput {
+    pub x: f32,
+    pub y: f32,
+    pub sprint: bool,
+    pub dash: bool, // one off, the server clears it once the dash happened
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InputCommand {
+    pub input: MoveInput,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WeaponCommand {
+    pub action: WeaponAction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum GameEventKind {
+    Explosion { radius: f32 },
+    BoxDestroyed,
+}
+
+// short lived, kept in a few snapshots in a row so clients don't miss them
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GameEvent {
+    pub kind: GameEventKind,
+    pub x: f32,
+    pub y: f32,
+    pub ticks_left: u32,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WoodBox {
     pub x: f32,
     pub y: f32,
+    pub health: f32,
+    pub max_health: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Wall {
+    pub x: f32,
+    pub y: f32,
+    pub kind: usize // index into Map::obstacle_types
 }
 
 impl Player {
-    pub fn respawn(&mut self) {
-        let mut rng = rand::thread_rng();
-        self.x = rng.gen_range(0..=WINDOW_SIZE_X - PLAYER_RADIUS as u32) as f32;
-        self.y = rng.gen_range(0..=WINDOW_SIZE_Y - PLAYER_RADIUS as u32) as f32;
+    pub fn new(id: u32, x: f32, y: f32) -> Self {
+        Player {
+            id,
+            x,
+            y,
+            has_flag: false,
+            respawn_num: 0,
+            score: 0,
+            health: PLAYER_MAX_HEALTH,
+            armor: PLAYER_SPAWN_ARMOR,
+            weapon: WeaponKind::Pistol,
+            weapons: Weapon::arsenal(),
+            effects: vec![],
+            spawn_protection: 0,
+            vx: 0.0,
+            vy: 0.0,
+            stamina: PLAYER_MAX_STAMINA,
+            dash_cooldown: 0,
+            input: MoveInput::default()
+        }
+    }
+
+    pub fn respawn(&mut self, x: f32, y: f32, spawn_protection: u32) {
+        self.x = x;
+        self.y = y;
+        self.vx = 0.0;
+        self.vy = 0.0;
+        self.stamina = PLAYER_MAX_STAMINA;
+        self.dash_cooldown = 0;
+        self.spawn_protection = spawn_protection;
         self.respawn_num += 1;
+        self.health = PLAYER_MAX_HEALTH;
+        self.armor = PLAYER_SPAWN_ARMOR;
+        self.weapon = WeaponKind::Pistol;
+        self.weapons = Weapon::arsenal();
+        self.effects.clear();
+    }
+
+    pub fn weapon(&self) -> &Weapon {
+        self.weapons.iter().find(|w| w.kind == self.weapon).unwrap()
+    }
+
+    pub fn weapon_mut(&mut self) -> &mut Weapon {
+        self.weapons.iter_mut().find(|w| w.kind == self.weapon).unwrap()
+    }
+
+    pub fn tick_weapons(&mut self) {
+        for weapon in &mut self.weapons {
+            weapon.tick();
+        }
+    }
+
+    pub fn has_effect(&self, kind: PickupKind) -> bool {
+        self.effects.iter().any(|e| e.kind == kind)
+    }
+
+    pub fn apply_pickup(&mut self, kind: PickupKind) {
+        if kind == PickupKind::HealthPack {
+            self.health = (self.health + HEALTH_PACK_HEAL).min(PLAYER_MAX_HEALTH);
+            return;
+        }
+
+        let ticks_left = kind.duration_ticks();
+        match self.effects.iter_mut().find(|e| e.kind == kind) {
+            Some(effect) => effect.ticks_left = ticks_left,
+            None => self.effects.push(Effect { kind, ticks_left })
+        }
+    }
+
+    pub fn tick_effects(&mut self) {
+        self.spawn_protection = self.spawn_protection.saturating_sub(1);
+        for effect in &mut self.effects {
+            effect.ticks_left = effect.ticks_left.saturating_sub(1);
+        }
+        self.effects.retain(|e| e.ticks_left > 0);
+    }
+
+    pub fn speed_multiplier(&self) -> f32 {
+        if self.has_effect(PickupKind::SpeedBoost) { SPEED_BOOST_MULTIPLIER } else { 1.0 }
+    }
+
+    pub fn fire(&mut self, dx: f32, dy: f32) -> Vec<Bullet> {
+        let rapid_fire = self.has_effect(PickupKind::RapidFire);
+        let weapon = self.weapon_mut();
+        if !weapon.fire() {
+            return vec![];
+        }
+        if rapid_fire {
+            weapon.cooldown /= 2;
+        }
+
+        let kind = weapon.kind;
+        self.spawn_protection = 0; // shooting gives up the protection
+        let stats = kind.stats();
+        let base_angle = dy.atan2(dx);
+        let first_offset = -(stats.pellets as f32 - 1.0) / 2.0;
+        (0..stats.pellets).map(|i| {
+            let angle = base_angle + (first_offset + i as f32) * stats.spread;
+            Bullet {
+                x: self.x,
+                y: self.y,
+                dx: angle.cos(),
+                dy: angle.sin(),
+                owner_id: self.id,
+                damage: stats.damage,
+                weapon: kind,
+                ticks: 0,
+                bounces: 0
+            }
+        }).collect()
+    }
+
+    // armor soaks part of every hit until it's gone, returns true when the hit was lethal
+    pub fn take_damage(&mut self, damage: f32) -> bool {
+        if self.has_effect(PickupKind::Shield) || self.spawn_protection > 0 {
+            return false;
+        }
+
+        let absorbed = (damage * ARMOR_ABSORPTION).min(self.armor);
+        self.armor -= absorbed;
+        self.health = (self.health - (damage - absorbed)).max(0.0);
+        self.health <= 0.0
     }
 }
 
-impl Bullet {
-    const SPEED: f32 = 20.0;
+impl Map {
+    pub fn obstacle_type(&self, wall: &Wall) -> &ObstacleType {
+        &self.obstacle_types[wall.kind]
+    }
+}
+
+impl WoodBox {
+    pub fn new(x: f32, y: f32, health: f32) -> Self {
+        WoodBox { x, y, health, max_health: health }
+    }
+}
+
+impl GameEvent {
+    pub fn new(kind: GameEventKind, x: f32, y: f32) -> Self {
+        GameEvent { kind, x, y, ticks_left: EVENT_TICKS }
+    }
+}
 
+impl Bullet {
     pub fn mov(&mut self) {
-        self.x += self.dx * Bullet::SPEED;
-        self.y += self.dy * Bullet::SPEED;
+        let speed = self.weapon.stats().bullet_speed;
+        self.x += self.dx * speed;
+        self.y += self.dy * speed;
+        self.ticks += 1;
+    }
+
+    pub fn is_explosive(&self) -> bool {
+        self.weapon.stats().blast_radius > 0.0
+    }
+
+    // flew its whole range, or a grenade whose fuse burnt down
+    pub fn is_expired(&self) -> bool {
+        let stats = self.weapon.stats();
+        self.ticks as f32 * stats.bullet_speed >= stats.range || matches!(stats.fuse_ticks, Some(fuse) if self.ticks >= fuse)
     }
 }
 
@@ -73,12 +393,22 @@ pub fn normalize(vec: (f32, f32)) -> (f32, f32) {
     }
 }
 
-pub async fn send_command<T>(writer: &mut OwnedWriteHalf, cmd: &[u8], obj: &T) where T: Serialize {
+pub fn encode_command<T>(cmd: &[u8], obj: &T) -> Vec<u8> where T: Serialize {
     let mut message = Vec::new();
     message.extend_from_slice(cmd);
     message.extend_from_slice(serde_json::to_string(obj).unwrap().as_bytes());
     message.extend_from_slice(CMD_SEPARATOR);
-    writer.write_all(&message).await.unwrap();
+    message
+}
+
+// splits off every complete command, partial tail stays in the buffer until the rest arrives
+pub fn take_commands(pending: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut commands = vec![];
+    while let Some(end) = pending.windows(CMD_SEPARATOR.len()).position(|w| w == CMD_SEPARATOR) {
+        commands.push(pending[..end].to_vec());
+        pending.drain(..end + CMD_SEPARATOR.len());
+    }
+    commands
 }
 
 pub fn get_distance(x1: f32, x2: f32, y1: f32, y2: f32) -> f32 {
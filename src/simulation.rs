@@ -0,0 +1,477 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::shared::*;
+use crate::pickup::*;
+use crate::config::*;
+use crate::spawn::*;
+use crate::map::*;
+use crate::collision::*;
+use crate::grid::*;
+use crate::movement::*;
+use crate::command::*;
+use crate::rotation::*;
+use crate::lobby::*;
+use crate::random::*;
+
+const FREE_TILE_TRIES: u32 = 1000;
+
+struct BoxRespawnTimer {
+    x: f32,
+    y: f32,
+    ticks_left: u64
+}
+
+#[derive(Default)]
+pub struct SimulationState {
+    flag_score_timer: u64,
+    pickup_timer: u64,
+    box_respawns: Vec<BoxRespawnTimer>,
+    pub kills: Vec<(u32, u32)>, // killer and victim, this tick only
+    pub grid: SpatialGrid // where everything ended up this tick, the bots look through it too
+}
+
+// one tick of a match, wherever it runs: the commands since the last one, the simulation, match end,
+// then the bots decide, their commands are for the next tick
+pub fn tick(
+    game_state: &mut GameState,
+    simulation: &mut SimulationState,
+    bots: &mut BotManager,
+    commands: impl IntoIterator<Item = (u32, Command)>,
+    config: &ServerConfig
+) -> Vec<(u32, Command)> {
+    for (player_id, command) in commands {
+        apply_command(game_state, player_id, command);
+    }
+    if game_state.match_info.phase != MatchPhase::Playing {
+        return vec![];
+    }
+    simulate(game_state, simulation, config);
+    update_match(game_state, config);
+    // bots see the same state everyone else is sent
+    bots.update(game_state, &simulation.grid)
+}
+
+pub fn simulate(game_state: &mut GameState, simulation: &mut SimulationState, config: &ServerConfig) {
+    for player in &mut game_state.players {
+        player.tick_weapons();
+        player.tick_effects();
+    }
+    // built once a tick, whatever moves or comes and goes later gets refreshed in it
+    let mut grid = SpatialGrid::build(game_state);
+    move_players(game_state, &grid);
+    grid.refresh_players(&game_state.players);
+    update_pickups(game_state, &mut simulation.pickup_timer, &mut grid);
+    for event in &mut game_state.events {
+        event.ticks_left = event.ticks_left.saturating_sub(1);
+    }
+    game_state.events.retain(|e| e.ticks_left > 0);
+
+    let mut detonations = vec![];
+    simulation.kills.clear();
+    let GameState { players, bullets, boxes, map, .. } = &mut *game_state;
+    bullets.retain_mut(|bullet| {
+        let from = (bullet.x, bullet.y);
+        bullet.mov();
+        let to = (bullet.x, bullet.y);
+
+        match first_bullet_hit(from, to, bullet.owner_id, players, boxes, map, &grid) {
+            Some((t, hit)) => {
+                bullet.x = from.0 + (to.0 - from.0) * t;
+                bullet.y = from.1 + (to.1 - from.1) * t;
+
+                let surface = match hit {
+                    Entry::Wall(index) if map.obstacle_type(&map.walls[index]).bullets == BulletBehavior::Ricochet => Some(map.walls[index].shape()),
+                    Entry::Box(index) => Some(boxes[index].shape()),
+                    _ => None
+                };
+                if let Some(surface) = surface.filter(|_| bullet.bounces < bullet.weapon.stats().bounces) {
+                    if let (Entry::Box(index), false) = (hit, bullet.is_explosive()) {
+                        boxes[index].health -= bullet.damage;
+                    }
+                    ricochet(bullet, &surface, from);
+                    return true;
+                }
+
+                if bullet.is_explosive() {
+                    detonations.push(bullet.clone());
+                    return false;
+                }
+                match hit {
+                    Entry::Box(index) => boxes[index].health -= bullet.damage,
+                    Entry::Player(index) if apply_damage(&mut players[index], bullet.damage) => {
+                        simulation.kills.push((bullet.owner_id, players[index].id));
+                    },
+                    _ => ()
+                }
+                false
+            },
+            None if bullet.is_expired() => {
+                if bullet.is_explosive() {
+                    detonations.push(bullet.clone());
+                }
+                false
+            },
+            None => true
+        }
+    });
+
+    for grenade in detonations {
+        let radius = grenade.weapon.stats().blast_radius;
+        let blast_damage = |distance: f32| grenade.damage * (1.0 - 0.5 * distance / radius); // half damage at the edge
+        for entry in grid.query_circle(&Circle { x: grenade.x, y: grenade.y, radius }) {
+            match entry {
+                Entry::Player(index) => {
+                    let player = &mut game_state.players[index];
+                    let distance = get_distance(player.x, grenade.x, player.y, grenade.y);
+                    if player.id != grenade.owner_id && distance < radius && apply_damage(player, blast_damage(distance)) {
+                        simulation.kills.push((grenade.owner_id, player.id));
+                    }
+                },
+                Entry::Box(index) => {
+                    let box_item = &mut game_state.boxes[index];
+                    let distance = box_item.shape().distance_to(grenade.x, grenade.y);
+                    if distance < radius {
+                        box_item.health -= blast_damage(distance);
+                    }
+                },
+                _ => ()
+            }
+        }
+        game_state.events.push(GameEvent::new(GameEventKind::Explosion { radius }, grenade.x, grenade.y));
+    }
+
+    for &(killer, victim) in &simulation.kills {
+        if let Some(shooter) = game_state.players.iter_mut().find(|p| p.id == killer) {
+            shooter.score += 1;
+        }
+        game_state.drop_flag(victim);
+    }
+    update_flag(game_state, &mut simulation.flag_score_timer);
+    update_boxes(game_state, &mut simulation.box_respawns, config, &mut grid);
+
+    respawn_dead_players(game_state, config, &mut grid);
+
+    let (width, height) = (game_state.map.width, game_state.map.height);
+    game_state.bullets.retain(|b| b.x >= 0.0 && b.x <= width && b.y >= 0.0 && b.y <= height);
+    simulation.grid = grid;
+}
+
+// only walls and boxes stop players, those stay put while everyone moves
+fn move_players(game_state: &mut GameState, grid: &SpatialGrid) {
+    let GameState { players, boxes, map, .. } = game_state;
+    for player in players.iter_mut() {
+        let fastest = (player.max_speed() * SPRINT_SPEED_MULTIPLIER).max(DASH_SPEED);
+        let reach = Circle { radius: PLAYER_RADIUS + fastest, ..player.shape() };
+        let obstacles: Vec<Rect> = grid.query_circle(&reach).into_iter()
+            .filter_map(|entry| match entry {
+                Entry::Box(index) => Some(boxes[index].shape()),
+                Entry::Wall(index) => Some(map.walls[index].shape()),
+                _ => None
+            })
+            .collect();
+        player.step(&obstacles, (map.width, map.height));
+    }
+}
+
+// whatever the bullet touches first on its way this tick, fast bullets can't skip over anything
+fn first_bullet_hit(from: (f32, f32), to: (f32, f32), owner_id: u32, players: &[Player], boxes: &[WoodBox], map: &Map, grid: &SpatialGrid) -> Option<(f32, Entry)> {
+    grid.query_segment(from, to).into_iter()
+        .filter_map(|entry| {
+            let t = match entry {
+                Entry::Wall(index) if map.obstacle_type(&map.walls[index]).bullets != BulletBehavior::Pass => segment_rect(from, to, &map.walls[index].shape()),
+                Entry::Box(index) => segment_rect(from, to, &boxes[index].shape()),
+                Entry::Player(index) if players[index].id != owner_id => segment_circle(from, to, &players[index].shape()),
+                _ => None
+            };
+            t.map(|t| (t, entry))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+// back off the surface a bit so the next tick doesn't start inside it, then fly off mirrored
+fn ricochet(bullet: &mut Bullet, surface: &Rect, from: (f32, f32)) {
+    let (back_x, back_y) = normalize((from.0 - bullet.x, from.1 - bullet.y));
+    bullet.x += back_x * 0.1;
+    bullet.y += back_y * 0.1;
+    (bullet.dx, bullet.dy) = reflect((bullet.dx, bullet.dy), surface, (bullet.x, bullet.y));
+    bullet.bounces += 1;
+}
+
+// once every hit of the tick landed: the flag sticks to its carrier however fast they go,
+// only a flag nobody holds can be picked up, by whoever's still standing on it
+fn update_flag(game_state: &mut GameState, flag_score_timer: &mut u64) {
+    if game_state.match_info.mode != GameMode::FlagHold {
+        return;
+    }
+
+    let carrier = game_state.flag_owner_id
+        .and_then(|id| game_state.players.iter().find(|p| p.id == id && p.has_flag))
+        .map(|p| (p.x, p.y));
+    match carrier {
+        Some((x, y)) => {
+            game_state.flag_x = x;
+            game_state.flag_y = y;
+        },
+        None => {
+            game_state.flag_owner_id = None;
+            let flag = game_state.flag_shape();
+            if let Some(player) = game_state.players.iter_mut().find(|p| p.health > 0.0 && p.shape().overlaps(&flag)) {
+                player.has_flag = true;
+                game_state.flag_owner_id = Some(player.id);
+                game_state.flag_x = player.x;
+                game_state.flag_y = player.y;
+                *flag_score_timer = 0;
+            }
+        }
+    }
+
+    *flag_score_timer += 1;
+    if *flag_score_timer > TICKS_PER_SECOND {
+        *flag_score_timer = 0;
+        if let Some(player) = game_state.players.iter_mut().find(|p| p.has_flag) {
+            player.score += 1;
+        }
+    }
+}
+
+pub fn start_match(game_state: &mut GameState, config: &ServerConfig, rotation_index: usize) {
+    let entry = &config.rotation[rotation_index];
+    let map = load_map(entry.map.as_deref());
+
+    game_state.flag_x = map.flag.0;
+    game_state.flag_y = map.flag.1;
+    game_state.flag_owner_id = None;
+    game_state.map = map;
+    game_state.bullets.clear();
+    game_state.boxes.clear();
+    game_state.events.clear();
+    game_state.pickups.clear();
+    game_state.match_info = MatchInfo {
+        mode: entry.mode,
+        ticks_left: config.match_secs * TICKS_PER_SECOND,
+        score_limit: config.score_limit,
+        ..Default::default()
+    };
+    place_boxes(game_state, config);
+
+    let grid = SpatialGrid::build(game_state);
+    let ids: Vec<u32> = game_state.players.iter().map(|p| p.id).collect();
+    for id in ids {
+        let (x, y) = choose_spawn_point(game_state, &grid, id);
+        if let Some(player) = game_state.players.iter_mut().find(|p| p.id == id) {
+            player.respawn(x, y, spawn_protection_ticks(config));
+            player.score = 0;
+            player.has_flag = false;
+        }
+    }
+}
+
+pub fn add_player(game_state: &mut GameState, player_id: u32, config: &ServerConfig) {
+    let (x, y) = choose_spawn_point(game_state, &SpatialGrid::build(game_state), player_id);
+    let mut new_player = Player::new(player_id, x, y);
+    new_player.spawn_protection = spawn_protection_ticks(config);
+    game_state.players.push(new_player);
+}
+
+fn apply_damage(player: &mut Player, damage: f32) -> bool {
+    if player.health <= 0.0 || !player.take_damage(damage) { // already went down earlier this tick
+        return false;
+    }
+
+    player.score -= 1;
+    true
+}
+
+fn respawn_dead_players(game_state: &mut GameState, config: &ServerConfig, grid: &mut SpatialGrid) {
+    let dead_ids: Vec<u32> = game_state.players.iter().filter(|p| p.health <= 0.0).map(|p| p.id).collect();
+    for id in dead_ids {
+        let (x, y) = choose_spawn_point(game_state, grid, id);
+        if let Some(player) = game_state.players.iter_mut().find(|p| p.id == id) {
+            player.respawn(x, y, spawn_protection_ticks(config));
+        }
+        grid.refresh_players(&game_state.players);
+    }
+}
+
+fn spawn_protection_ticks(config: &ServerConfig) -> u32 {
+    (config.spawn_protection_secs * TICKS_PER_SECOND) as u32
+}
+
+fn update_pickups(game_state: &mut GameState, pickup_timer: &mut u64, grid: &mut SpatialGrid) {
+    let GameState { players, pickups, .. } = game_state;
+    let count = pickups.len();
+    pickups.retain(|pickup| {
+        let collector = players.iter_mut().find(|p| p.shape().overlaps(&pickup.shape()));
+        match collector {
+            Some(player) => {
+                player.apply_pickup(pickup.kind);
+                false
+            },
+            None => true
+        }
+    });
+    if pickups.len() != count {
+        grid.refresh_pickups(pickups);
+    }
+
+    *pickup_timer += 1;
+    if *pickup_timer < PICKUP_SPAWN_TICKS {
+        return;
+    }
+    *pickup_timer = 0;
+
+    if game_state.pickups.len() < MAX_PICKUPS {
+        let free_spots: Vec<(f32, f32)> = game_state.map.pickup_spots.iter()
+            .filter(|(x, y)| is_area_free(&Rect::square(*x, *y, BOX_SIZE), game_state, grid))
+            .cloned()
+            .collect();
+        let (x, y) = match free_spots.choose(&mut rng()) {
+            Some(spot) => *spot,
+            None if game_state.map.pickup_spots.is_empty() => match find_free_tile(game_state, grid) {
+                Some(tile) => tile.center(),
+                None => return
+            },
+            None => return // every spot of the map is taken
+        };
+        let kind = PickupKind::ALL[rng().gen_range(0..PickupKind::ALL.len())];
+        let pickup = Pickup { x, y, kind };
+        grid.insert(Entry::Pickup(game_state.pickups.len()), &Rect::square(x, y, PICKUP_SIZE));
+        game_state.pickups.push(pickup);
+    }
+}
+
+fn update_boxes(game_state: &mut GameState, box_respawns: &mut Vec<BoxRespawnTimer>, config: &ServerConfig, grid: &mut SpatialGrid) {
+    let GameState { boxes, events, .. } = &mut *game_state;
+    let count = boxes.len();
+    boxes.retain(|b| {
+        if b.health > 0.0 {
+            return true;
+        }
+
+        let (x, y) = b.shape().center();
+        events.push(GameEvent::new(GameEventKind::BoxDestroyed, x, y));
+        box_respawns.push(BoxRespawnTimer { x: b.x, y: b.y, ticks_left: config.box_respawn_secs * TICKS_PER_SECOND });
+        false
+    });
+    if boxes.len() != count {
+        grid.refresh_boxes(boxes);
+    }
+
+    for timer in box_respawns.iter_mut() {
+        timer.ticks_left = timer.ticks_left.saturating_sub(1);
+    }
+    if box_respawns.iter().all(|t| t.ticks_left > 0) {
+        return;
+    }
+
+    while let Some(index) = box_respawns.iter().position(|t| t.ticks_left == 0) {
+        let timer = box_respawns.remove(index);
+        let tile = match config.box_respawn {
+            BoxRespawn::Original if is_area_free(&Rect::tile(timer.x, timer.y), game_state, grid) => Rect::tile(timer.x, timer.y),
+            _ => match find_free_tile(game_state, grid) {
+                Some(tile) => tile,
+                None => continue // the arena is packed, that box stays gone
+            }
+        };
+        grid.insert(Entry::Box(game_state.boxes.len()), &tile);
+        game_state.boxes.push(WoodBox::new(tile.x, tile.y, config.box_health));
+    }
+}
+
+fn place_boxes(game_state: &mut GameState, config: &ServerConfig) {
+    for (x, y) in game_state.map.boxes.clone() {
+        game_state.boxes.push(WoodBox::new(x, y, config.box_health));
+    }
+
+    let mut grid = SpatialGrid::build(game_state);
+    for _ in 0..config.box_count {
+        let Some(tile) = find_free_tile(game_state, &grid) else { break }; // no room for more
+        grid.insert(Entry::Box(game_state.boxes.len()), &tile);
+        game_state.boxes.push(WoodBox::new(tile.x, tile.y, config.box_health));
+    }
+}
+
+// random tile sized area nothing stands on, not necessarily aligned to the map grid,
+// None once enough random tries failed, a packed map would keep it looking forever
+pub fn find_free_tile(game_state: &GameState, grid: &SpatialGrid) -> Option<Rect> {
+    let mut rng = rng();
+    let (columns, rows) = ((game_state.map.width - BOX_SIZE).max(1.0) as u32, (game_state.map.height - BOX_SIZE).max(1.0) as u32);
+    (0..FREE_TILE_TRIES)
+        .map(|_| Rect::tile(rng.gen_range(0..columns) as f32, rng.gen_range(0..rows) as f32))
+        .find(|tile| is_area_free(tile, game_state, grid))
+}
+
+fn is_area_free(area: &Rect, game_state: &GameState, grid: &SpatialGrid) -> bool {
+    grid.query(area).into_iter().all(|entry| match entry {
+        Entry::Player(index) => !game_state.players[index].shape().overlaps_rect(area),
+        Entry::Box(index) => !game_state.boxes[index].shape().overlaps(area),
+        Entry::Wall(index) => !game_state.map.walls[index].shape().overlaps(area),
+        Entry::Pickup(index) => !game_state.pickups[index].shape().overlaps_rect(area)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bullet_hits_nearer_obstacle() {
+        let mut game_state = GameState::default();
+        game_state.map.width = 400.0;
+        game_state.map.height = 400.0;
+        game_state.boxes = vec![WoodBox::new(100.0, 0.0, 50.0), WoodBox::new(50.0, 0.0, 50.0)];
+        let grid = SpatialGrid::build(&game_state);
+
+        let hit = first_bullet_hit((0.0, 10.0), (150.0, 10.0), 1, &game_state.players, &game_state.boxes, &game_state.map, &grid);
+        assert!(matches!(hit, Some((t, Entry::Box(1))) if (t - 50.0 / 150.0).abs() < 1e-4));
+    }
+
+    #[test]
+    fn flag_follows_a_dashing_carrier() {
+        let mut game_state = GameState::default();
+        game_state.map.width = 400.0;
+        game_state.map.height = 400.0;
+        game_state.match_info.mode = GameMode::FlagHold;
+        let mut carrier = Player::new(1, 200.0, 204.0); // a dash away from the flag
+        carrier.has_flag = true;
+        game_state.players = vec![carrier, Player::new(2, 200.0, 310.0)];
+        game_state.flag_owner_id = Some(1);
+        (game_state.flag_x, game_state.flag_y) = (200.0, 310.0);
+
+        simulate(&mut game_state, &mut SimulationState::default(), &ServerConfig::default());
+        assert_eq!((game_state.flag_x, game_state.flag_y), (200.0, 204.0));
+        assert_eq!(game_state.flag_owner_id, Some(1));
+        assert!(!game_state.players[1].has_flag);
+    }
+
+    #[test]
+    fn packed_map_places_no_boxes() {
+        let map = crate::map::parse_map(r####"{ "name": "packed", "tiles": ["###", "###", "###"] }"####).unwrap();
+        let mut game_state = GameState { map, ..Default::default() };
+        place_boxes(&mut game_state, &ServerConfig { box_count: 12, ..Default::default() });
+        assert!(game_state.boxes.is_empty());
+    }
+
+    #[test]
+    fn killed_carrier_frees_the_flag() {
+        let mut game_state = GameState::default();
+        game_state.map.width = 400.0;
+        game_state.map.height = 400.0;
+        game_state.match_info.mode = GameMode::Deathmatch; // nobody picks it up again, only the drop counts
+        let mut carrier = Player::new(1, 200.0, 220.0);
+        carrier.has_flag = true;
+        carrier.health = 1.0;
+        carrier.armor = 0.0;
+        let mut shooter = Player::new(2, 200.0, 200.0);
+        game_state.bullets = shooter.fire(0.0, 1.0);
+        game_state.players = vec![carrier, shooter];
+        game_state.flag_owner_id = Some(1);
+
+        let mut simulation = SimulationState::default();
+        simulate(&mut game_state, &mut simulation, &ServerConfig::default());
+        assert_eq!(simulation.kills, vec![(2, 1)]);
+        assert_eq!(game_state.flag_owner_id, None);
+        assert!(!game_state.players[0].has_flag);
+    }
+}
@@ -0,0 +1,43 @@
+use rand::seq::SliceRandom;
+
+use crate::shared::*;
+use crate::collision::*;
+use crate::grid::*;
+use crate::random::*;
+
+const MIN_FLAG_DISTANCE: f32 = 60.0;
+
+// picks the free spawn point furthest away from every enemy, random free spot if all of them are blocked,
+// and if there's none of those either, the first spawn point or the middle of the map
+pub fn choose_spawn_point(game_state: &GameState, grid: &SpatialGrid, player_id: u32) -> (f32, f32) {
+    let mut candidates: Vec<&SpawnPoint> = game_state.map.spawn_points.iter()
+        .filter(|sp| is_spawn_point_free(game_state, grid, sp))
+        .collect();
+    candidates.shuffle(&mut rng()); // so ties don't always go to the first point
+
+    let enemy_distance = |sp: &SpawnPoint| {
+        game_state.players.iter()
+            .filter(|p| p.id != player_id)
+            .map(|p| get_distance(p.x, sp.x, p.y, sp.y))
+            .fold(f32::MAX, f32::min)
+    };
+
+    match candidates.into_iter().max_by(|a, b| enemy_distance(a).total_cmp(&enemy_distance(b))) {
+        Some(sp) => (sp.x, sp.y),
+        None => match crate::simulation::find_free_tile(game_state, grid) {
+            Some(tile) => tile.center(),
+            None => game_state.map.spawn_points.first()
+                .map_or((game_state.map.width / 2.0, game_state.map.height / 2.0), |sp| (sp.x, sp.y))
+        }
+    }
+}
+
+fn is_spawn_point_free(game_state: &GameState, grid: &SpatialGrid, sp: &SpawnPoint) -> bool {
+    let shape = Circle { x: sp.x, y: sp.y, radius: PLAYER_RADIUS };
+    let blocked = grid.query_circle(&shape).into_iter().any(|entry| match entry {
+        Entry::Box(index) => shape.overlaps_rect(&game_state.boxes[index].shape()),
+        Entry::Wall(index) => shape.overlaps_rect(&game_state.map.walls[index].shape()),
+        _ => false
+    });
+    !blocked && get_distance(game_state.flag_x, sp.x, game_state.flag_y, sp.y) > MIN_FLAG_DISTANCE
+}
@@ -0,0 +1,222 @@
+// bots against bots without clients, sockets or waiting for the next tick, as many matches as configured,
+// then a report of how every entrant did
+
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use sfml_example::shared::*;
+use sfml_example::config::*;
+use sfml_example::bot::*;
+use sfml_example::brain::Brain;
+use sfml_example::lobby::*;
+use sfml_example::simulation::*;
+
+const TOURNAMENT_PATH: &str = "tournament.json";
+
+// one side of the tournament, the brain and everything else a bot slot in the server config takes
+#[derive(Deserialize, Clone, Debug)]
+struct Entrant {
+    name: String,
+    #[serde(flatten)]
+    slot: BotSlot
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+struct TournamentConfig {
+    entrants: Vec<Entrant>,
+    bots_per_entrant: usize, // every entrant fields this many bots in every match
+    matches: usize, // played through the rotation of the rules one after another
+    report: String, // written to <report>.json and <report>.csv
+    rules: ServerConfig // maps, modes, match length and boxes, the bot settings are ignored
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        let entrant = |name: &str, brain| Entrant { name: name.to_string(), slot: BotSlot { brain, ..Default::default() } };
+        TournamentConfig {
+            entrants: vec![entrant("utility", Brain::Utility), entrant("rusher", Brain::Rusher)],
+            bots_per_entrant: 1,
+            matches: 10,
+            report: "tournament".to_string(),
+            rules: ServerConfig::default()
+        }
+    }
+}
+
+// how one entrant did in one match, all its bots together
+#[derive(Serialize, Clone, Default)]
+struct EntrantResult {
+    score: i32,
+    kills: u32,
+    deaths: u32,
+    flag_ticks: u64
+}
+
+struct MatchResult {
+    rotation: String,
+    ticks: u64,
+    winner: Option<usize>, // index into the entrants, None - draw
+    results: Vec<EntrantResult>
+}
+
+// a match as the report shows it, by then the winner is a name, results stay in entrant order
+#[derive(Serialize)]
+struct MatchReport<'a> {
+    rotation: &'a str,
+    ticks: u64,
+    winner: Option<&'a str>,
+    results: &'a [EntrantResult]
+}
+
+#[derive(Serialize, Default)]
+struct Standing {
+    name: String,
+    matches: u32,
+    wins: u32,
+    draws: u32,
+    win_rate: f32,
+    score: i32,
+    kills: u32,
+    deaths: u32,
+    flag_secs: f32
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    standings: Vec<Standing>,
+    matches: Vec<MatchReport<'a>>
+}
+
+fn main() {
+    let config: TournamentConfig = load_json_or_default(TOURNAMENT_PATH);
+    if config.entrants.is_empty() || config.bots_per_entrant == 0 {
+        println!("Nobody to play, add entrants to {TOURNAMENT_PATH}");
+        return;
+    }
+
+    let started = Instant::now();
+    let mut matches = vec![];
+    for index in 0..config.matches {
+        let match_started = Instant::now();
+        let result = play_match(&config, index);
+        let game_secs = result.ticks as f32 / TICKS_PER_SECOND as f32;
+        let winner = result.winner.map_or("nobody", |w| config.entrants[w].name.as_str());
+        println!(
+            "Match {}/{} {} won by {winner} - {game_secs:.0} s played in {:.2} s",
+            index + 1, config.matches, result.rotation, match_started.elapsed().as_secs_f32()
+        );
+        matches.push(result);
+    }
+
+    let standings = standings(&config, &matches);
+    for s in &standings {
+        println!("{:<16} {:>3}/{} won, {} score, {} kills, {} deaths, {:.0} s with the flag", s.name, s.wins, s.matches, s.score, s.kills, s.deaths, s.flag_secs);
+    }
+    println!("{} matches in {:.1} s", matches.len(), started.elapsed().as_secs_f32());
+    let matches = matches.iter()
+        .map(|m| MatchReport {
+            rotation: &m.rotation,
+            ticks: m.ticks,
+            winner: m.winner.map(|w| config.entrants[w].name.as_str()),
+            results: &m.results
+        })
+        .collect();
+    write_report(&config.report, &Report { standings, matches });
+}
+
+fn play_match(config: &TournamentConfig, index: usize) -> MatchResult {
+    let mut rules = config.rules.clone();
+    rules.bots = config.entrants.iter().flat_map(|e| vec![e.slot; config.bots_per_entrant]).collect();
+    rules.min_players = 0;
+    let rotation_index = index % rules.rotation.len();
+
+    let mut game_state = GameState::default();
+    let mut bots = BotManager::new(&rules);
+    start_match(&mut game_state, &rules, rotation_index);
+    bots.balance(&mut game_state, &IdAllocator::new(), &rules);
+
+    let player_ids = bots.player_ids();
+    let entrant_of = |player_id: u32| player_ids.iter().position(|id| *id == player_id).map(|slot| slot / config.bots_per_entrant);
+    let mut results = vec![EntrantResult::default(); config.entrants.len()];
+    let mut simulation = SimulationState::default();
+    let mut commands = vec![];
+    let mut ticks = 0;
+
+    while game_state.match_info.phase == MatchPhase::Playing {
+        ticks += 1;
+        commands = tick(&mut game_state, &mut simulation, &mut bots, commands, &rules);
+
+        for &(killer, victim) in &simulation.kills {
+            if let Some(entrant) = entrant_of(killer) {
+                results[entrant].kills += 1;
+            }
+            if let Some(entrant) = entrant_of(victim) {
+                results[entrant].deaths += 1;
+            }
+        }
+        if let Some(entrant) = game_state.players.iter().find(|p| p.has_flag).and_then(|p| entrant_of(p.id)) {
+            results[entrant].flag_ticks += 1;
+        }
+    }
+
+    for player in &game_state.players {
+        if let Some(entrant) = entrant_of(player.id) {
+            results[entrant].score += player.score;
+        }
+    }
+    // the best team score wins, a shared best is a draw
+    let best = results.iter().map(|r| r.score).max().unwrap_or(0);
+    let leaders: Vec<usize> = (0..results.len()).filter(|&i| results[i].score == best).collect();
+    MatchResult {
+        rotation: rules.rotation[rotation_index].label(),
+        ticks,
+        winner: match leaders.as_slice() {
+            [winner] => Some(*winner),
+            _ => None
+        },
+        results
+    }
+}
+
+fn standings(config: &TournamentConfig, matches: &[MatchResult]) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = config.entrants.iter()
+        .map(|e| Standing { name: e.name.clone(), ..Default::default() })
+        .collect();
+    for result in matches {
+        for (index, (standing, entrant)) in standings.iter_mut().zip(&result.results).enumerate() {
+            standing.matches += 1;
+            standing.score += entrant.score;
+            standing.kills += entrant.kills;
+            standing.deaths += entrant.deaths;
+            standing.flag_secs += entrant.flag_ticks as f32 / TICKS_PER_SECOND as f32;
+            match result.winner {
+                Some(winner) if winner == index => standing.wins += 1,
+                None if entrant.score == result.results.iter().map(|r| r.score).max().unwrap_or(0) => standing.draws += 1,
+                _ => ()
+            }
+        }
+    }
+    for standing in &mut standings {
+        standing.win_rate = standing.wins as f32 / standing.matches.max(1) as f32;
+    }
+    standings.sort_by(|a, b| b.win_rate.total_cmp(&a.win_rate).then(b.score.cmp(&a.score)));
+    standings
+}
+
+fn write_report(path: &str, report: &Report) {
+    let json = serde_json::to_string_pretty(report).unwrap();
+    if let Err(e) = std::fs::write(format!("{path}.json"), json) {
+        println!("Failed to write {path}.json: {e}");
+    }
+
+    let mut csv = String::from("name,matches,wins,draws,win_rate,score,kills,deaths,flag_secs\n");
+    for s in &report.standings {
+        csv += &format!(
+            "\"{}\",{},{},{},{:.3},{},{},{},{:.1}\n",
+            s.name.replace('"', "\"\""), s.matches, s.wins, s.draws, s.win_rate, s.score, s.kills, s.deaths, s.flag_secs
+        );
+    }
+    if let Err(e) = std::fs::write(format!("{path}.csv"), csv) {
+        println!("Failed to write {path}.csv: {e}");
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeaponKind {
+    Pistol,
+    Shotgun,
+    Rifle,
+    Grenade
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum WeaponAction {
+    Switch(WeaponKind),
+    Reload
+}
+
+pub struct WeaponStats {
+    pub cooldown_ticks: u32,
+    pub reload_ticks: u32,
+    pub magazine: u32,
+    pub reserve: Option<u32>, // None - never runs out
+    pub bullet_speed: f32,
+    pub damage: f32,
+    pub pellets: u32,
+    pub spread: f32, // radians between two neighbouring pellets
+    pub blast_radius: f32,
+    pub fuse_ticks: Option<u32>,
+    pub range: f32,
+    pub bounces: u32 // ricochets off walls and boxes before it stops
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    pub ammo: u32,
+    pub reserve: Option<u32>,
+    pub cooldown: u32,
+    pub reload: u32
+}
+
+impl WeaponKind {
+    pub const ALL: [WeaponKind; 4] = [WeaponKind::Pistol, WeaponKind::Shotgun, WeaponKind::Rifle, WeaponKind::Grenade];
+
+    pub fn stats(self) -> WeaponStats {
+        match self {
+            WeaponKind::Pistol => WeaponStats {
+                cooldown_ticks: 8,
+                reload_ticks: 30,
+                magazine: 12,
+                reserve: None,
+                bullet_speed: 20.0,
+                damage: 25.0,
+                pellets: 1,
+                spread: 0.0,
+                blast_radius: 0.0,
+                fuse_ticks: None,
+                range: 600.0,
+                bounces: 0
+            },
+            WeaponKind::Shotgun => WeaponStats {
+                cooldown_ticks: 20,
+                reload_ticks: 48,
+                magazine: 6,
+                reserve: Some(24),
+                bullet_speed: 16.0,
+                damage: 12.0,
+                pellets: 6,
+                spread: 0.1,
+                blast_radius: 0.0,
+                fuse_ticks: None,
+                range: 250.0,
+                bounces: 0
+            },
+            WeaponKind::Rifle => WeaponStats {
+                cooldown_ticks: 30,
+                reload_ticks: 60,
+                magazine: 5,
+                reserve: Some(20),
+                bullet_speed: 35.0,
+                damage: 45.0,
+                pellets: 1,
+                spread: 0.0,
+                blast_radius: 0.0,
+                fuse_ticks: None,
+                range: 1000.0,
+                bounces: 1
+            },
+            WeaponKind::Grenade => WeaponStats {
+                cooldown_ticks: 24,
+                reload_ticks: 24,
+                magazine: 1,
+                reserve: Some(3),
+                bullet_speed: 8.0,
+                damage: 60.0,
+                pellets: 1,
+                spread: 0.0,
+                blast_radius: 60.0,
+                fuse_ticks: Some(24),
+                range: 400.0,
+                bounces: 2
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WeaponKind::Pistol => "Pistol",
+            WeaponKind::Shotgun => "Shotgun",
+            WeaponKind::Rifle => "Rifle",
+            WeaponKind::Grenade => "Grenade"
+        }
+    }
+}
+
+impl Weapon {
+    pub fn new(kind: WeaponKind) -> Self {
+        let stats = kind.stats();
+        Weapon {
+            kind,
+            ammo: stats.magazine,
+            reserve: stats.reserve,
+            cooldown: 0,
+            reload: 0
+        }
+    }
+
+    pub fn arsenal() -> Vec<Weapon> {
+        WeaponKind::ALL.iter().map(|k| Weapon::new(*k)).collect()
+    }
+
+    pub fn tick(&mut self) {
+        self.cooldown = self.cooldown.saturating_sub(1);
+        if self.reload > 0 {
+            self.reload -= 1;
+            if self.reload == 0 {
+                self.finish_reload();
+            }
+        }
+    }
+
+    pub fn has_ammo(&self) -> bool {
+        self.ammo > 0 || self.reserve != Some(0)
+    }
+
+    pub fn can_fire(&self) -> bool {
+        self.cooldown == 0 && self.reload == 0 && self.ammo > 0
+    }
+
+    pub fn fire(&mut self) -> bool {
+        if !self.can_fire() {
+            return false;
+        }
+
+        self.ammo -= 1;
+        self.cooldown = self.kind.stats().cooldown_ticks;
+        if self.ammo == 0 {
+            self.start_reload();
+        }
+        true
+    }
+
+    pub fn start_reload(&mut self) {
+        let stats = self.kind.stats();
+        if self.reload > 0 || self.ammo == stats.magazine || self.reserve == Some(0) {
+            return;
+        }
+        self.reload = stats.reload_ticks;
+    }
+
+    fn finish_reload(&mut self) {
+        let missing = self.kind.stats().magazine - self.ammo;
+        let loaded = match self.reserve {
+            Some(reserve) => missing.min(reserve),
+            None => missing
+        };
+        self.ammo += loaded;
+        if let Some(reserve) = &mut self.reserve {
+            *reserve -= loaded;
+        }
+    }
+}